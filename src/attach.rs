@@ -0,0 +1,195 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, Read, Seek, SeekFrom, Write},
+    os::unix::fs::MetadataExt,
+    path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    config::{Project, StdinMode, WorkerConfig},
+    logs::{LogFilter, LogReader},
+};
+
+const TICK: Duration = Duration::from_millis(100);
+// Looking for the processes of the project is slow, so it is not done on every tick
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+// Lines of the log printed when attaching, to show where the project is at. Only the end of the
+// log is read to find them
+const CONTEXT_LINES: usize = 10;
+const CONTEXT_BYTES: u64 = 64 * 1024;
+// Typed on a line of its own, like in ssh
+const DETACH: &str = "~.";
+
+/// Connect the terminal to a running project with `stdin = "pipe"`. Lines typed are sent to
+/// the project, and its output is printed as it is written to the log. Runs until detached with
+/// `~.` or the end of the input, or until the project exits
+pub fn run(config: &WorkerConfig, project: Project) -> Result<(), anyhow::Error> {
+    // The project as it was started, in case the config has changed since
+    let running = config
+        .running()?
+        .into_iter()
+        .find(|it| it.project.name == project.name)
+        .ok_or_else(|| anyhow!("{} is not running", project))?;
+    if running.foreground {
+        return Err(anyhow!(
+            "Can't attach to {}, it is run in the foreground of another terminal",
+            project
+        ));
+    }
+    let project = running.project;
+    if project.stdin != StdinMode::Pipe {
+        return Err(anyhow!(
+            "Can't attach to {}, it doesn't read input. Set stdin = \"pipe\" and restart it",
+            project
+        ));
+    }
+
+    let mut pipe = OpenOptions::new()
+        .write(true)
+        .open(config.stdin_file(&project))?;
+
+    let mut output = Output::new(config.log_file(&project), project.capture())?;
+
+    eprintln!(
+        "Attached to {}. Detach with {} on a line of its own, or Ctrl-D",
+        project, DETACH
+    );
+
+    let (detached, detach) = mpsc::channel();
+    std::thread::spawn(move || {
+        let res = (|| {
+            for line in std::io::stdin().lock().lines() {
+                let line = line?;
+                if line.trim() == DETACH {
+                    break;
+                }
+                pipe.write_all(format!("{}\n", line).as_bytes())?;
+            }
+            Ok(())
+        })();
+        let _ = detached.send(res);
+    });
+
+    let mut stdout = std::io::stdout().lock();
+    let mut checked_at = Instant::now();
+    loop {
+        output.print(&mut stdout)?;
+        stdout.flush()?;
+
+        match detach.recv_timeout(TICK) {
+            Ok(res) => {
+                // Output written in response to the last line may still be on its way
+                std::thread::sleep(TICK);
+                output.print(&mut stdout)?;
+                eprintln!("Detached from {}", project);
+                return res;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("Stopped reading the input"));
+            }
+        }
+
+        if checked_at.elapsed() < CHECK_INTERVAL {
+            continue;
+        }
+        checked_at = Instant::now();
+        if !config.is_running(&project)? {
+            output.print(&mut stdout)?;
+            eprintln!("{} has exited", project);
+            return Ok(());
+        }
+    }
+}
+
+// The output of the project. Captured logs are read a line at a time, and the rest as it is
+// written, to show prompts that don't end with a newline
+enum Output {
+    Lines(LogReader),
+    Raw {
+        path: PathBuf,
+        file: File,
+        position: u64,
+    },
+}
+
+impl Output {
+    fn new(path: PathBuf, capture: bool) -> Result<Self, anyhow::Error> {
+        if capture {
+            let mut reader = LogReader::new(path);
+            let lines = reader.tail(CONTEXT_LINES, &LogFilter::default())?;
+            for line in lines {
+                println!("{}", line.text);
+            }
+            return Ok(Output::Lines(reader));
+        }
+
+        let mut file = File::open(&path)?;
+        let offset = file.metadata()?.len().saturating_sub(CONTEXT_BYTES);
+        file.seek(SeekFrom::Start(offset))?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+
+        // The last lines, including the line being written, which is likely a prompt
+        let start = content
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, it)| **it == b'\n')
+            .nth(CONTEXT_LINES)
+            .map_or(0, |(i, _)| i + 1);
+        std::io::stdout().write_all(&content[start..])?;
+
+        Ok(Output::Raw {
+            path,
+            file,
+            position: offset + content.len() as u64,
+        })
+    }
+
+    fn print(&mut self, out: &mut impl Write) -> Result<(), anyhow::Error> {
+        match self {
+            Output::Lines(reader) => {
+                for line in reader.poll(&LogFilter::default())? {
+                    writeln!(out, "{}", line.text)?;
+                }
+            }
+            Output::Raw {
+                path,
+                file,
+                position,
+            } => {
+                // The log is truncated or replaced when the project is restarted. When it is
+                // rotated on start, there is no log until the new one is created
+                let replaced = match std::fs::metadata(&path) {
+                    Ok(it) => it.ino() != file.metadata()?.ino(),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+                    Err(e) => return Err(e.into()),
+                };
+                if file.metadata()?.len() < *position {
+                    *position = 0;
+                }
+
+                file.seek(SeekFrom::Start(*position))?;
+                let mut buf = Vec::new();
+                *position += file.read_to_end(&mut buf)? as u64;
+                out.write_all(&buf)?;
+
+                // What was written before the log was replaced is printed first
+                if replaced {
+                    *file = File::open(&path)?;
+                    *position = 0;
+                    let mut buf = Vec::new();
+                    *position += file.read_to_end(&mut buf)? as u64;
+                    out.write_all(&buf)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,19 +1,41 @@
-use std::{collections::HashMap, fs::File, hash::Hash, path::PathBuf, str::FromStr};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions, TryLockError},
+    hash::Hash,
+    os::unix::{fs::FileTypeExt, process::CommandExt},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use anyhow::{anyhow, Context};
 use itertools::{Either, Itertools};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    libc::{has_processes_running, stop_pg, Signal},
-    ActionArg,
+    daemon::now,
+    env,
+    libc::{
+        getsid, has_processes_running, is_process_running, mkfifo, signal_tree, stop_pg,
+        ExitStatus, Signal,
+    },
+    logs,
+    schedule::Schedule,
+    shim, ActionArg,
 };
 
-const CONFIG_FILE: &str = ".worker.toml";
+pub const CONFIG_FILE: &str = ".worker.toml";
+const LOCAL_CONFIG_FILE: &str = ".worker.local.toml";
+const DEFAULT_STOP_TIMEOUT: u64 = 5;
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub project: Vec<Project>,
+    /// Log settings used by every project, unless the project overrides them
+    pub log: Option<LogConfig>,
+    /// Env files loaded for every project, before the env files of the project
+    pub env_file: Option<Vec<String>>,
 }
 
 pub trait WorkerProject {
@@ -25,24 +47,209 @@ pub trait WorkerProject {
 pub struct Project {
     pub name: String,
     pub command: String,
+    #[serde(default)]
+    pub kind: ProjectKind,
+    /// When the daemon should run the task
+    pub schedule: Option<Schedule>,
+    /// Relative to the config dir, which is also the default
+    #[serde(default)]
     pub cwd: String,
     pub display: Option<String>,
     pub stop_signal: Option<Signal>,
+    pub stop_sequence: Option<Vec<StopStep>>,
+    pub stop_timeout: Option<u64>,
     pub envs: Option<HashMap<String, String>>,
+    /// `.env` files relative to the config dir. Variables in `envs` take precedence
+    pub env_file: Option<Vec<String>>,
     pub group: Option<Vec<String>>,
+    pub depends_on: Option<Vec<String>>,
+    pub healthcheck: Option<HealthCheck>,
+    pub log: Option<LogConfig>,
+    pub restart: Option<RestartPolicy>,
+    pub max_restarts: Option<u32>,
+    pub restart_backoff_ms: Option<u64>,
+    pub restart_reset_secs: Option<u64>,
+    pub watch: Option<WatchConfig>,
+    #[serde(default)]
+    pub stdin: StdinMode,
+    /// Run the command in a pseudo-terminal, so it writes its output the way it does in a
+    /// terminal, e.g. with colors
+    pub tty: Option<bool>,
+}
+
+/// Whether the project keeps running, or runs to completion
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectKind {
+    #[default]
+    Service,
+    /// Runs to completion, like a migration or a cleanup job
+    Task,
+}
+
+/// Where the project reads its input from
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StdinMode {
+    /// Nothing, the project sees the end of its input right away
+    #[default]
+    Null,
+    /// A named pipe kept open for as long as the project runs, written to by `worker attach`
+    Pipe,
+}
+
+/// Signal to send if the project is still running `after` seconds after it was asked to stop
+#[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
+pub struct StopStep {
+    pub signal: Signal,
+    pub after: u64,
+}
+
+/// How to check that a project is ready to be used. Exactly one of `tcp`, `http`, `command` or
+/// `log` should be set
+#[derive(Deserialize, Serialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct HealthCheck {
+    /// Address to connect to, e.g. `localhost:8080`
+    pub tcp: Option<String>,
+    /// Url to send a GET request to, e.g. `http://localhost:8080/health`
+    pub http: Option<String>,
+    /// Expected status code of the http check. Any 2xx status if not set
+    pub status: Option<u16>,
+    /// Command that should exit successfully
+    pub command: Option<String>,
+    /// Regex that should match a line in the log file
+    pub log: Option<String>,
+    pub interval_ms: Option<u64>,
+    pub timeout_secs: Option<u64>,
+}
+
+/// Files to watch with `worker watch`, to restart the project when they change
+#[derive(Deserialize, Serialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct WatchConfig {
+    /// Files and directories relative to `cwd`. Directories are watched recursively. Defaults to
+    /// `cwd`
+    pub paths: Option<Vec<String>>,
+    /// Glob patterns of files to not restart for. Patterns without a `/` match the name of the
+    /// file or of any directory it is in, the others match the path relative to `cwd`
+    pub ignore: Option<Vec<String>>,
+    /// How long to wait for more changes before restarting
+    pub debounce_ms: Option<u64>,
+}
+
+/// The last run of a project, kept after the project has stopped to tell how it stopped
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LastRun {
+    pub pid: i32,
+    /// Unix time in seconds
+    pub started_at: u64,
+    pub stopped_at: Option<u64>,
+    pub exit: Option<ExitStatus>,
+    /// The user who stopped the project. Not set when it exited by itself
+    pub stopped_by: Option<String>,
+}
+
+/// Rotation of the log file of a project
+#[derive(Deserialize, Serialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct LogConfig {
+    /// Rotate the log when it grows larger than this, e.g. `"10M"`. Checked by the daemon, or by
+    /// the process in front of the project when it was started without the daemon
+    #[serde(default, deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+    /// Number of previous logs to keep
+    pub keep: Option<u32>,
+    /// Rotate the log when the project is started, instead of truncating it
+    pub rotate_on_start: Option<bool>,
+    /// Compress previous logs with gzip, except the most recent one
+    pub compress: Option<bool>,
+    /// Capture the output through pipes, to store each line with the time and the stream it was
+    /// written to
+    pub capture: Option<bool>,
+}
+
+impl LogConfig {
+    // Fields not set are taken from `other`
+    fn merge(self, other: &LogConfig) -> LogConfig {
+        LogConfig {
+            max_size: self.max_size.or(other.max_size),
+            keep: self.keep.or(other.keep),
+            rotate_on_start: self.rotate_on_start.or(other.rotate_on_start),
+            compress: self.compress.or(other.compress),
+            capture: self.capture.or(other.capture),
+        }
+    }
 }
 
-/// Project with process id
+// Accept a number of bytes, or a string with a K, M or G suffix
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Human(String),
+    }
+
+    let size = match Option::<Size>::deserialize(deserializer)? {
+        None => return Ok(None),
+        Some(Size::Bytes(bytes)) => return Ok(Some(bytes)),
+        Some(Size::Human(size)) => size,
+    };
+
+    let size = size.trim();
+    let (number, unit) = match size.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => size.split_at(i),
+        None => (size, ""),
+    };
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1 << 10,
+        "M" | "MB" => 1 << 20,
+        "G" | "GB" => 1 << 30,
+        _ => return Err(serde::de::Error::custom(format!("Invalid size: {}", size))),
+    };
+
+    number
+        .parse::<u64>()
+        .map(|it| Some(it * multiplier))
+        .map_err(|_| serde::de::Error::custom(format!("Invalid size: {}", size)))
+}
+
+/// When the daemon should restart a project that has exited
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    Always,
+    OnFailure,
+    #[default]
+    Never,
+}
+
+/// Project with process id. Stored as the state of the project while it is running, which keeps
+/// the project as it was configured when it was started
 #[derive(Deserialize, Serialize, Clone, Debug, Eq, PartialEq)]
 pub struct RunningProject {
-    pub name: String,
-    pub command: String,
-    pub cwd: String,
-    pub display: Option<String>,
-    pub stop_signal: Option<Signal>,
-    pub envs: Option<HashMap<String, String>>,
-    pub group: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub project: Project,
+    /// Pid of the session leader, which is also the session id
     pub pid: i32,
+    /// Number of times the daemon has restarted the project after it exited
+    #[serde(default)]
+    pub restarts: u32,
+    pub last_exit: Option<ExitStatus>,
+    /// Unix time in milliseconds
+    #[serde(default)]
+    pub started_at_ms: u64,
+    /// The program and arguments that were run
+    #[serde(default)]
+    pub argv: Vec<String>,
+    /// The boot the project was started in. The pid belongs to another process after a reboot
+    pub boot_id: Option<String>,
+    /// Run in the foreground with `worker run`. The pid is then the process itself, which stays
+    /// in the session of the terminal
+    #[serde(default)]
+    pub foreground: bool,
 }
 
 impl Hash for Project {
@@ -51,58 +258,49 @@ impl Hash for Project {
     }
 }
 
-macro_rules! impl_display {
-    ($project:tt) => {
-        impl std::fmt::Display for $project {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-                if let Some(ref display) = self.display {
-                    write!(f, "{} ({})", display, self.name)
-                } else {
-                    write!(f, "{}", self.name)
-                }
-            }
+impl std::fmt::Display for Project {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ref display) = self.display {
+            write!(f, "{} ({})", display, self.name)
+        } else {
+            write!(f, "{}", self.name)
         }
-    };
+    }
 }
 
-macro_rules! impl_worker_project {
-    ($project:tt) => {
-        impl WorkerProject for $project {
-            fn name(&self) -> &str {
-                &self.name
-            }
-        }
-    };
-}
+impl Project {
+    /// Whether the output is written to the log a line at a time, with the time and the stream
+    pub fn capture(&self) -> bool {
+        self.log.as_ref().and_then(|it| it.capture).unwrap_or(false)
+    }
 
-impl_display!(Project);
-impl_display!(RunningProject);
+    /// Whether the project runs with a pseudo-terminal as its output
+    pub fn tty(&self) -> bool {
+        self.tty.unwrap_or(false)
+    }
+}
 
-impl_worker_project!(Project);
-impl_worker_project!(RunningProject);
+impl std::fmt::Display for RunningProject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.project.fmt(f)
+    }
+}
 
-fn find_project(name: &str) -> Result<Project, anyhow::Error> {
-    let config = WorkerConfig::new()?;
-    let projects: Vec<String> = config.projects.iter().map(|p| p.name.clone()).collect();
+impl WorkerProject for Project {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
 
-    config
-        .projects
-        .into_iter()
-        .find(|it| it.name == name)
-        .with_context(|| format!("Valid projects are {:#?}", projects))
+impl WorkerProject for RunningProject {
+    fn name(&self) -> &str {
+        &self.project.name
+    }
 }
 
 impl From<RunningProject> for Project {
     fn from(value: RunningProject) -> Self {
-        Self {
-            name: value.name,
-            command: value.command,
-            cwd: value.cwd,
-            display: value.display,
-            stop_signal: value.stop_signal,
-            envs: value.envs,
-            group: value.group,
-        }
+        value.project
     }
 }
 
@@ -125,7 +323,10 @@ impl FromStr for ActionArg {
         if !projects_in_group.is_empty() {
             Ok(ActionArg::Group(projects_in_group))
         } else if let Some(project) = config.projects.iter().find(|it| it.name == s) {
-            Ok(ActionArg::Project(project.clone()))
+            Ok(ActionArg::Project(Box::new(project.clone())))
+        } else if let Some(running) = config.running()?.into_iter().find(|it| it.name() == s) {
+            // Removed from the config while it was running
+            Ok(ActionArg::Project(Box::new(running.project)))
         } else {
             let project_names: Vec<String> =
                 config.projects.iter().map(|p| p.name.clone()).collect();
@@ -147,65 +348,198 @@ impl FromStr for ActionArg {
     }
 }
 
-impl FromStr for Project {
-    type Err = anyhow::Error;
+impl RunningProject {
+    pub fn new(project: Project, pid: i32) -> Self {
+        let argv = shlex::split(&project.command).unwrap_or_default();
+        Self {
+            project,
+            pid,
+            restarts: 0,
+            last_exit: None,
+            started_at_ms: now_ms(),
+            argv,
+            boot_id: boot_id(),
+            foreground: false,
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        find_project(s)
+    pub fn signal(&self, signal: &Signal) -> Result<(), anyhow::Error> {
+        match self.foreground {
+            true => signal_tree(self.pid, signal),
+            false => stop_pg(self.pid, signal),
+        }
+        .map_err(|_| anyhow!("Error trying to stop project"))
     }
-}
 
-impl FromStr for RunningProject {
-    type Err = anyhow::Error;
+    pub fn is_running(&self) -> bool {
+        match self.foreground {
+            true => is_process_running(self.pid),
+            false => has_processes_running(self.pid),
+        }
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (name, pid) = s.rsplit_once('-').context("No - in string")?;
-        let project = find_project(name)?;
-
-        Ok(RunningProject {
-            name: project.name,
-            command: project.command,
-            cwd: project.cwd,
-            display: project.display,
-            stop_signal: project.stop_signal,
-            envs: project.envs,
-            group: project.group,
-            pid: pid.parse().context("Couldn't parse pid")?,
-        })
+    /// The session the project runs in
+    pub fn sid(&self) -> i32 {
+        match self.foreground {
+            true => getsid(self.pid).unwrap_or(self.pid),
+            false => self.pid,
+        }
     }
-}
 
-impl RunningProject {
-    pub fn stop(&self) -> Result<(), anyhow::Error> {
-        let signal = self.stop_signal.as_ref().unwrap_or(&Signal::SIGINT);
-        stop_pg(self.pid, signal).map_err(|_| anyhow!("Error trying to stop project"))
+    /// Signals to send when stopping the project, and when to send them
+    pub fn stop_sequence(&self, force: bool) -> Vec<(Duration, Signal)> {
+        if force {
+            return vec![(Duration::ZERO, Signal::SIGKILL)];
+        }
+
+        let signal = self.project.stop_signal.unwrap_or(Signal::SIGINT);
+        let steps = self.project.stop_sequence.iter().flatten();
+
+        std::iter::once((Duration::ZERO, signal))
+            .chain(steps.map(|it| (Duration::from_secs(it.after), it.signal)))
+            .sorted_by_key(|(after, _)| *after)
+            .collect()
+    }
+
+    /// How long to wait for the project to stop before giving up
+    pub fn stop_deadline(&self, force: bool) -> Duration {
+        let last = self
+            .stop_sequence(force)
+            .iter()
+            .map(|(after, _)| *after)
+            .max()
+            .unwrap_or_default();
+
+        last + Duration::from_secs(self.project.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT))
     }
 }
 
 pub struct WorkerConfig {
     pub projects: Vec<Project>,
+    worker_dir: PathBuf,
     state_dir: PathBuf,
     log_dir: PathBuf,
+    runs_dir: PathBuf,
+    lock_dir: PathBuf,
+    stdin_dir: PathBuf,
+}
+
+/// Lock of a project, released when dropped
+pub struct ProjectLock(File);
+
+impl Drop for ProjectLock {
+    // Processes forked while the lock is held share it, so it is unlocked explicitly instead of
+    // waiting for every one of them to close it
+    fn drop(&mut self) {
+        let _ = self.0.unlock();
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+// Changes on every boot. Not available on every platform
+fn boot_id() -> Option<String> {
+    std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .ok()
+        .map(|it| it.trim().to_string())
+}
+
+// The name is encoded to be usable as a file name. Dots are encoded too, so that state files
+// don't start with a dot like the temporary files
+fn state_file_name(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len() + 5);
+    for byte in name.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'_' | b'-' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded.push_str(".json");
+    encoded
+}
+
+// Read a state file. Files named `name-pid` are from before the state was stored as a record,
+// and only contain the project
+fn read_state(path: &Path) -> Option<RunningProject> {
+    let content = std::fs::read_to_string(path).ok()?;
+    if let Ok(project) = serde_json::from_str::<RunningProject>(&content) {
+        return Some(project);
+    }
+
+    let (name, pid) = path.file_name()?.to_str()?.rsplit_once('-')?;
+    let project: Project = serde_json::from_str(&content).ok()?;
+    if project.name != name {
+        return None;
+    }
+
+    // The file was written when the project was started
+    let modified = std::fs::metadata(path).and_then(|it| it.modified()).ok()?;
+    Some(RunningProject {
+        started_at_ms: modified.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64,
+        boot_id: None,
+        ..RunningProject::new(project, pid.parse().ok()?)
+    })
+}
+
+// Write to a temporary file that is renamed over `path`, so that nobody reads a file that is
+// only partly written
+fn write_atomic(path: &Path, content: &[u8]) -> Result<(), anyhow::Error> {
+    let name = path.file_name().context("No file name")?.to_string_lossy();
+    let tmp = path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&tmp);
+    })?;
+
+    Ok(())
 }
 
 impl WorkerConfig {
     pub fn new() -> Result<Self, anyhow::Error> {
         let base_dir = find_config_dir()?.context("Couldn't find config dir")?;
-        let config_string = std::fs::read_to_string(base_dir.join(CONFIG_FILE))?;
 
-        let state_dir = base_dir.join(".worker/state");
-        let log_dir = base_dir.join(".worker/log");
+        let worker_dir = base_dir.join(".worker");
+        let state_dir = worker_dir.join("state");
+        let log_dir = worker_dir.join("log");
+        let runs_dir = worker_dir.join("runs");
+        let lock_dir = worker_dir.join("lock");
+        let stdin_dir = worker_dir.join("stdin");
 
         std::fs::create_dir_all(&state_dir)?;
         std::fs::create_dir_all(&log_dir)?;
+        std::fs::create_dir_all(&runs_dir)?;
+        std::fs::create_dir_all(&lock_dir)?;
+        std::fs::create_dir_all(&stdin_dir)?;
 
-        // Deserialize the TOML string into the Config struct
-        let config: Config = toml::from_str(&config_string)?;
+        let mut config: Config = load(&base_dir)?
+            .try_into()
+            .context("Invalid config. Run `worker validate` to find where")?;
+        validate_dependencies(&config.project)?;
+        validate_healthchecks(&config.project)?;
+        validate_schedules(&config.project)?;
+
+        let log = config.log.unwrap_or_default();
+        let env_files = config.env_file.unwrap_or_default();
+        // Variables that are not set are left for the shell, and reported by `worker validate`
+        for project in config.project.iter_mut() {
+            resolve_project(project, &base_dir, &log, &env_files)
+                .with_context(|| format!("Invalid config for {}", project.name))?;
+        }
 
         Ok(Self {
             projects: config.project,
+            worker_dir,
             state_dir,
             log_dir,
+            runs_dir,
+            lock_dir,
+            stdin_dir,
         })
     }
 
@@ -213,30 +547,253 @@ impl WorkerConfig {
         self.log_dir.join(&project.name)
     }
 
-    pub fn store_state(&self, pid: i32, project: &Project) -> Result<(), anyhow::Error> {
-        let filename = format!("{}-{}", project.name, pid);
-        let state_file = self.state_dir.join(filename);
+    /// Where the state and logs of the projects are kept
+    pub fn worker_dir(&self) -> &Path {
+        &self.worker_dir
+    }
+
+    /// The named pipe the project reads its input from, with `stdin = "pipe"`
+    pub fn stdin_file(&self, project: &Project) -> PathBuf {
+        self.stdin_dir.join(&project.name)
+    }
+
+    pub fn socket_file(&self) -> PathBuf {
+        self.worker_dir.join("daemon.sock")
+    }
+
+    pub fn daemon_log_file(&self) -> PathBuf {
+        self.worker_dir.join("daemon.log")
+    }
 
-        let file = File::create(state_file).expect("Couldn't create state file");
-        serde_json::to_writer(file, &project).expect("Couldn't write to state file");
+    /// Build the command for a project with its log file as stdout and stderr. Rotates or
+    /// truncates the previous log
+    pub fn command(&self, project: &Project) -> Result<Command, anyhow::Error> {
+        let mut cmd = self.bare_command(project)?;
+        logs::rotate_on_start(self, project)?;
+
+        // Share the same file description for stdout and stderr to merge them. Appending makes
+        // it possible to truncate the file while the project is writing to it
+        let stdout = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.log_file(project))?;
+        let stderr = stdout.try_clone()?;
+
+        cmd.stdout(stdout)
+            .stderr(stderr)
+            .stdin(self.stdin(project)?);
+
+        Ok(cmd)
+    }
+
+    // The pipe is opened for writing as well, so the project doesn't see the end of its input
+    // when nobody is attached
+    fn stdin(&self, project: &Project) -> Result<Stdio, anyhow::Error> {
+        if project.stdin == StdinMode::Null {
+            return Ok(Stdio::null());
+        }
+
+        let path = self.stdin_file(project);
+        if !path.metadata().is_ok_and(|it| it.file_type().is_fifo()) {
+            let _ = std::fs::remove_file(&path);
+            mkfifo(&path).map_err(|_| {
+                anyhow!(
+                    "Couldn't create {}: {}",
+                    path.display(),
+                    std::io::Error::last_os_error()
+                )
+            })?;
+        }
+
+        Ok(OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)?
+            .into())
+    }
+
+    /// Build the command for a project with its args, envs and cwd, leaving the stdio as is
+    pub fn bare_command(&self, project: &Project) -> Result<Command, anyhow::Error> {
+        let parts = shlex::split(&project.command)
+            .context(format!("Couldn't parse command: {}", project.command))?;
+        let program = parts
+            .first()
+            .context(format!("Empty command for {}", project.name))?;
+
+        let mut cmd = Command::new(program);
+        cmd.args(&parts[1..])
+            .envs(project.envs.clone().unwrap_or_default())
+            .current_dir(&project.cwd);
+
+        Ok(cmd)
+    }
+
+    /// Replace the current process with the project. Only returns if that fails. When the output
+    /// is captured or goes through a tty, or the exit should be recorded, the process stays in
+    /// front of the project instead, and exits the same way as the project
+    pub fn exec(&self, project: &Project, record: bool) -> anyhow::Error {
+        let mut cmd = match self.command(project) {
+            Ok(cmd) => cmd,
+            Err(e) => return e,
+        };
+
+        if project.capture() || record || project.tty() {
+            match shim::run(self, project, cmd, record) {
+                Ok(never) => match never {},
+                Err(e) => e,
+            }
+        } else {
+            cmd.exec().into()
+        }
+    }
+
+    pub fn store_state(&self, project: &RunningProject) -> Result<(), anyhow::Error> {
+        write_atomic(
+            &self.state_file(&project.project),
+            &serde_json::to_vec(&project)?,
+        )
+        .context("Couldn't write state file")
+    }
+
+    /// Hold the lock of the project until the returned value is dropped, to make sure only one
+    /// process starts it. Waits for the lock if it is held by another process
+    pub fn lock(&self, project: &Project) -> Result<ProjectLock, anyhow::Error> {
+        let file = self.lock_file(project)?;
+        file.lock()?;
+
+        Ok(ProjectLock(file))
+    }
+
+    /// Like `lock`, but returns `None` instead of waiting if the lock is held by another process
+    pub fn try_lock(&self, project: &Project) -> Result<Option<ProjectLock>, anyhow::Error> {
+        let file = self.lock_file(project)?;
+        match file.try_lock() {
+            Ok(()) => Ok(Some(ProjectLock(file))),
+            Err(TryLockError::WouldBlock) => Ok(None),
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+
+    fn lock_file(&self, project: &Project) -> Result<File, anyhow::Error> {
+        Ok(OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.lock_dir.join(&project.name))?)
+    }
+
+    pub fn state_file(&self, project: &Project) -> PathBuf {
+        self.state_dir.join(state_file_name(&project.name))
+    }
+
+    /// How long the project has been running
+    pub fn uptime(&self, project: &RunningProject) -> Option<Duration> {
+        Some(Duration::from_millis(
+            now_ms().checked_sub(project.started_at_ms)?,
+        ))
+    }
+
+    /// Remove the state of the project, unless it has been started again with another pid
+    pub fn remove_state(&self, pid: i32, project: &Project) -> Result<(), anyhow::Error> {
+        // State files used to be named after the project and the pid
+        let legacy = self.state_dir.join(format!("{}-{}", project.name, pid));
+        let path = self.state_file(project);
+        let stored = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|it| serde_json::from_str::<RunningProject>(&it).ok());
+
+        for path in [
+            Some(legacy),
+            stored.filter(|it| it.pid == pid).map(|_| path),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
 
         Ok(())
     }
 
+    /// The last run of the project, if it has been started
+    pub fn last_run(&self, project: &Project) -> Option<LastRun> {
+        let content = std::fs::read_to_string(self.runs_dir.join(&project.name)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn store_last_run(&self, project: &Project, run: &LastRun) -> Result<(), anyhow::Error> {
+        write_atomic(
+            &self.runs_dir.join(&project.name),
+            &serde_json::to_vec(run)?,
+        )
+    }
+
+    pub fn record_start(&self, project: &Project, pid: i32) -> Result<(), anyhow::Error> {
+        self.store_last_run(
+            project,
+            &LastRun {
+                pid,
+                started_at: now(),
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Record that the run has ended. `exit` is not known when the project is found to be gone,
+    /// without anyone having seen it exit
+    pub fn record_exit(
+        &self,
+        project: &Project,
+        pid: i32,
+        exit: Option<ExitStatus>,
+    ) -> Result<(), anyhow::Error> {
+        let Some(mut run) = self.last_run(project).filter(|it| it.pid == pid) else {
+            return Ok(());
+        };
+
+        run.stopped_at = run.stopped_at.or(Some(now()));
+        run.exit = exit.or(run.exit);
+        self.store_last_run(project, &run)
+    }
+
+    /// Record that the run is being stopped by the current user
+    pub fn record_stop(&self, project: &Project, pid: i32) -> Result<(), anyhow::Error> {
+        let Some(mut run) = self.last_run(project).filter(|it| it.pid == pid) else {
+            return Ok(());
+        };
+
+        let user = std::env::var("USER").or_else(|_| std::env::var("LOGNAME"));
+        run.stopped_by = Some(user.unwrap_or("unknown".to_string()));
+        self.store_last_run(project, &run)
+    }
+
     pub fn is_running(&self, project: &Project) -> Result<bool, anyhow::Error> {
-        Ok(self.running()?.iter().any(|it| it.name == project.name))
+        Ok(self.running()?.iter().any(|it| it.name() == project.name))
     }
 
-    // Try to get vec of running projects. Try to remove the state file if the process is not running
+    // Try to get vec of running projects, from the state files whether or not the projects are
+    // still in the config. Try to remove the state file if the process is not running
     pub fn running(&self) -> Result<Vec<RunningProject>, anyhow::Error> {
+        let boot_id = boot_id();
         let projects = std::fs::read_dir(self.state_dir.as_path())?
             .filter_map(|entry| {
                 let path = entry.ok()?.path();
-                let project = RunningProject::from_str(path.file_name()?.to_str()?).ok()?;
-                if has_processes_running(project.pid) {
+                // Files still being written
+                if path.file_name()?.to_str()?.starts_with('.') {
+                    return None;
+                }
+
+                let project = read_state(&path)?;
+                let rebooted = project.boot_id.is_some() && project.boot_id != boot_id;
+                if !rebooted && project.is_running() {
                     Some(project)
                 } else {
-                    let _ = std::fs::remove_file(&path);
+                    // The project may have been started again since the file was read
+                    let _ = self.remove_state(project.pid, &project.project);
+                    let _ = self.record_exit(&project.project, project.pid, None);
                     None
                 }
             })
@@ -245,6 +802,60 @@ impl WorkerConfig {
         Ok(projects)
     }
 
+    /// Order projects so that dependencies come before the projects depending on them. Pulls
+    /// in dependencies that are not in `projects`
+    pub fn with_dependencies(&self, projects: Vec<Project>) -> Vec<Project> {
+        fn visit(config: &WorkerConfig, project: Project, ordered: &mut Vec<Project>) {
+            if ordered.iter().any(|it| it.name == project.name) {
+                return;
+            }
+
+            for dependency in project.depends_on.iter().flatten() {
+                if let Some(dependency) = config.projects.iter().find(|it| &it.name == dependency) {
+                    visit(config, dependency.clone(), ordered);
+                }
+            }
+
+            ordered.push(project);
+        }
+
+        let mut ordered = Vec::new();
+        for project in projects {
+            visit(self, project, &mut ordered);
+        }
+
+        ordered
+    }
+
+    /// Split projects into batches to stop one after another, so that a project is stopped
+    /// before the projects it depends on
+    pub fn stop_order(&self, projects: Vec<RunningProject>) -> Vec<Vec<RunningProject>> {
+        let dependencies: HashMap<_, _> = projects
+            .iter()
+            .map(|it| {
+                let mut dependencies = self.with_dependencies(vec![it.project.clone()]);
+                dependencies.pop();
+                (it.project.name.clone(), dependencies)
+            })
+            .collect();
+
+        let mut batches = Vec::new();
+        let mut remaining = projects;
+        while !remaining.is_empty() {
+            let (batch, rest): (Vec<_>, Vec<_>) = remaining.iter().cloned().partition(|it| {
+                !remaining.iter().any(|other| {
+                    dependencies[&other.project.name]
+                        .iter()
+                        .any(|dependency| dependency.name == it.project.name)
+                })
+            });
+            batches.push(batch);
+            remaining = rest;
+        }
+
+        batches
+    }
+
     pub fn partition_projects<T>(
         &self,
         projects: Vec<T>,
@@ -255,7 +866,7 @@ impl WorkerConfig {
         // Partition map to get project with pid set
         let running_projects = self.running()?;
         let (running, not_running): (Vec<_>, Vec<_>) = projects.into_iter().partition_map(|rp| {
-            match running_projects.iter().find(|p| p.name == rp.name()) {
+            match running_projects.iter().find(|p| p.name() == rp.name()) {
                 Some(p) => Either::Left(p.to_owned()),
                 None => Either::Right(rp.into()),
             }
@@ -265,8 +876,408 @@ impl WorkerConfig {
     }
 }
 
+pub fn validate_healthchecks(projects: &[Project]) -> Result<(), anyhow::Error> {
+    for project in projects {
+        let Some(ref healthcheck) = project.healthcheck else {
+            continue;
+        };
+
+        let checks = [
+            healthcheck.tcp.is_some(),
+            healthcheck.http.is_some(),
+            healthcheck.command.is_some(),
+            healthcheck.log.is_some(),
+        ];
+        if checks.iter().filter(|it| **it).count() != 1 {
+            return Err(anyhow!(
+                "Healthcheck for {} needs exactly one of tcp, http, command or log",
+                project.name
+            ));
+        }
+
+        if let Some(ref log) = healthcheck.log {
+            regex::Regex::new(log).with_context(|| {
+                format!("Invalid log regex in healthcheck for {}", project.name)
+            })?;
+        }
+
+        if let Some(ref url) = healthcheck.http {
+            if !url.starts_with("http://") {
+                return Err(anyhow!(
+                    "Healthcheck for {} only supports http:// urls",
+                    project.name
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn validate_schedules(projects: &[Project]) -> Result<(), anyhow::Error> {
+    for project in projects {
+        if project.schedule.is_some() && project.kind != ProjectKind::Task {
+            return Err(anyhow!(
+                "{} has a schedule, but only tasks can be scheduled. Set kind = \"task\"",
+                project.name
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+// Make sure every dependency exists and that there are no cycles between them
+pub fn validate_dependencies(projects: &[Project]) -> Result<(), anyhow::Error> {
+    fn visit<'a>(
+        project: &'a Project,
+        projects: &'a [Project],
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<(), anyhow::Error> {
+        if done.contains(project.name.as_str()) {
+            return Ok(());
+        }
+
+        if let Some(i) = path.iter().position(|it| *it == project.name) {
+            return Err(anyhow!(
+                "Dependency cycle between projects: {} -> {}",
+                path[i..].join(" -> "),
+                project.name
+            ));
+        }
+
+        path.push(&project.name);
+        for dependency in project.depends_on.iter().flatten() {
+            let dependency = projects
+                .iter()
+                .find(|it| &it.name == dependency)
+                .with_context(|| {
+                    format!("{} depends on unknown project {}", project.name, dependency)
+                })?;
+            visit(dependency, projects, path, done)?;
+        }
+        path.pop();
+        done.insert(&project.name);
+
+        Ok(())
+    }
+
+    let mut done = HashSet::new();
+    for project in projects {
+        visit(project, projects, &mut Vec::new(), &mut done)?;
+    }
+
+    Ok(())
+}
+
+/// Fill in what the project takes from the rest of the config, and resolve its variables and
+/// `cwd`. Returns the variables that are used without being set
+pub fn resolve_project(
+    project: &mut Project,
+    base_dir: &Path,
+    log: &LogConfig,
+    env_files: &[String],
+) -> Result<Vec<UnsetVariable>, anyhow::Error> {
+    project.log = Some(project.log.take().unwrap_or_default().merge(log));
+    let unset = resolve_envs(project, base_dir, env_files)?;
+    project.cwd = resolve_path(base_dir, &project.cwd)
+        .to_string_lossy()
+        .to_string();
+
+    Ok(unset)
+}
+
+/// A variable used in the config without being set, and the field it is used in
+#[derive(Debug)]
+pub struct UnsetVariable {
+    pub field: Vec<String>,
+    pub name: String,
+}
+
+/// Load the env files of the project into `envs`, and interpolate variables in `command`, `cwd`
+/// and `envs`. Variables are looked up in `envs`, the env files, `WORKER_ROOT`, `PROJECT_NAME`
+/// and the environment, in that order
+fn resolve_envs(
+    project: &mut Project,
+    base_dir: &Path,
+    env_files: &[String],
+) -> Result<Vec<UnsetVariable>, anyhow::Error> {
+    let mut unset = Vec::new();
+    let mut found = |field: &[&str], names: &mut Vec<String>| {
+        for name in names.drain(..) {
+            let field = field.iter().map(|it| it.to_string()).collect();
+            unset.push(UnsetVariable { field, name });
+        }
+    };
+    let mut names = Vec::new();
+
+    let mut vars: HashMap<String, String> = std::env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .collect();
+    vars.insert(
+        "WORKER_ROOT".to_string(),
+        base_dir.to_string_lossy().to_string(),
+    );
+    vars.insert("PROJECT_NAME".to_string(), project.name.clone());
+
+    let mut envs = HashMap::new();
+    for file in env_files.iter().chain(project.env_file.iter().flatten()) {
+        for (key, value) in env::read_env_file(&resolve_path(base_dir, file), &vars, &mut names)? {
+            vars.insert(key.clone(), value.clone());
+            envs.insert(key, value);
+        }
+        found(&["env_file"], &mut names);
+    }
+
+    // Values in `envs` can only refer to variables outside of `envs`, since they are not ordered
+    let mut own = HashMap::new();
+    for (key, value) in project.envs.take().unwrap_or_default() {
+        let value = env::interpolate(&value, &vars, &mut names)
+            .with_context(|| format!("In envs.{}", key))?;
+        found(&["envs", &key], &mut names);
+        own.insert(key, value);
+    }
+    vars.extend(own.clone());
+    envs.extend(own);
+
+    project.command =
+        env::interpolate(&project.command, &vars, &mut names).context("In command")?;
+    found(&["command"], &mut names);
+    project.cwd = env::interpolate(&project.cwd, &vars, &mut names).context("In cwd")?;
+    found(&["cwd"], &mut names);
+    project.envs = (!envs.is_empty()).then_some(envs);
+
+    Ok(unset)
+}
+
+/// A config file as read, without `include`
+pub struct ConfigFile {
+    pub path: PathBuf,
+    pub content: String,
+    pub table: toml::Table,
+}
+
+/// A problem in a config file, at a line when it is known
+#[derive(Debug)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// The line number of a byte offset in `content`, starting at 1
+pub fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+/// Read the layers of the config in the order they should be merged, each on top of the ones
+/// before it:
+/// - The global config in `~/.config/worker/config.toml`, if it exists
+/// - `.worker.toml`
+/// - `.worker.local.toml` next to it, if it exists
+///
+/// Files named by `include` in a layer come before the file including them
+pub fn config_files(base_dir: &Path) -> Result<Vec<ConfigFile>, anyhow::Error> {
+    let mut files = Vec::new();
+
+    if let Some(global) = global_config_file().filter(|it| it.exists()) {
+        read_file(&global, &mut Vec::new(), &mut files)?;
+    }
+
+    read_file(&base_dir.join(CONFIG_FILE), &mut Vec::new(), &mut files)?;
+
+    let local = base_dir.join(LOCAL_CONFIG_FILE);
+    if local.exists() {
+        read_file(&local, &mut Vec::new(), &mut files)?;
+    }
+
+    Ok(files)
+}
+
+fn load(base_dir: &Path) -> Result<toml::Table, anyhow::Error> {
+    let mut config = toml::Table::new();
+    for file in config_files(base_dir)? {
+        merge(&mut config, file.table).with_context(|| file.path.display().to_string())?;
+    }
+
+    Ok(config)
+}
+
+fn global_config_file() -> Option<PathBuf> {
+    let config_dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_dir.join("worker").join("config.toml"))
+}
+
+// Read a config file after the files it includes. `including` is the files currently being
+// read, to catch files including each other
+fn read_file(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    files: &mut Vec<ConfigFile>,
+) -> Result<(), anyhow::Error> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    if including.contains(&path) {
+        return Err(anyhow!("{} includes itself", path.display()));
+    }
+    including.push(path.clone());
+
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Couldn't read {}", path.display()))?;
+    let mut table: toml::Table = toml::from_str(&content).map_err(|e| ConfigError {
+        path: path.clone(),
+        line: e.span().map(|it| line_of(&content, it.start)),
+        message: e.message().to_string(),
+    })?;
+
+    let includes: Vec<String> = match table.remove("include") {
+        Some(include) => include
+            .try_into()
+            .with_context(|| format!("include in {} should be a list of paths", path.display()))?,
+        None => Vec::new(),
+    };
+
+    // Includes are relative to the file including them
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    for pattern in includes {
+        let pattern = dir.join(expand_home(&pattern));
+        let pattern = pattern.to_string_lossy();
+        let mut included: Vec<_> = glob::glob(&pattern)
+            .with_context(|| format!("Invalid include {} in {}", pattern, path.display()))?
+            .collect::<Result<_, _>>()?;
+        included.sort();
+
+        if included.is_empty() && !pattern.contains(['*', '?', '[']) {
+            return Err(anyhow!(
+                "Included file {} in {} does not exist",
+                pattern,
+                path.display()
+            ));
+        }
+
+        for file in included {
+            read_file(&file, including, files)?;
+        }
+    }
+    including.pop();
+
+    files.push(ConfigFile {
+        path,
+        content,
+        table,
+    });
+
+    Ok(())
+}
+
+// Resolve a path relative to the config dir, with `~` expanded
+fn resolve_path(base_dir: &Path, path: &str) -> PathBuf {
+    base_dir
+        .join(expand_home(path))
+        .components()
+        .filter(|it| *it != std::path::Component::CurDir)
+        .collect()
+}
+
+// Expand a leading `~` to the home directory
+fn expand_home(path: &str) -> PathBuf {
+    let Some(home) = std::env::var_os("HOME") else {
+        return PathBuf::from(path);
+    };
+
+    match path.strip_prefix('~') {
+        Some("") => PathBuf::from(home),
+        Some(rest) if rest.starts_with('/') => PathBuf::from(home).join(&rest[1..]),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Merge `layer` on top of `config`:
+/// - Projects are matched by name. The fields of a project defined by an earlier layer are
+///   merged, and other projects are added
+/// - `log` and `envs` are merged key by key
+/// - Any other field is replaced, e.g. `group` or `healthcheck`
+///
+/// Projects with the same name in the layer itself are an error. The layer is still merged, with
+/// each of them added on its own, for `validate` to check all of them
+pub fn merge(config: &mut toml::Table, layer: toml::Table) -> Result<(), anyhow::Error> {
+    let mut duplicates = Vec::new();
+    for (key, value) in layer {
+        if key == "project" && value.is_array() && !config.contains_key(&key) {
+            config.insert(key.clone(), toml::Value::Array(Vec::new()));
+        }
+
+        match (key.as_str(), config.get_mut(&key), value) {
+            ("project", Some(toml::Value::Array(projects)), toml::Value::Array(layer)) => {
+                let earlier = projects.len();
+                let mut names = HashSet::new();
+                for project in layer {
+                    let name = project.get("name").and_then(|it| it.as_str());
+                    if name.is_some_and(|it| !names.insert(it.to_string())) {
+                        duplicates.extend(name.map(str::to_string));
+                        projects.push(project);
+                        continue;
+                    }
+
+                    let existing = projects[..earlier].iter_mut().find(|it| {
+                        it.get("name").is_some() && it.get("name") == project.get("name")
+                    });
+                    match (existing, project) {
+                        (Some(toml::Value::Table(existing)), toml::Value::Table(project)) => {
+                            merge_project(existing, project)
+                        }
+                        (_, project) => projects.push(project),
+                    }
+                }
+            }
+            ("log", Some(toml::Value::Table(log)), toml::Value::Table(layer)) => log.extend(layer),
+            (_, _, value) => {
+                config.insert(key, value);
+            }
+        }
+    }
+
+    match duplicates.as_slice() {
+        [] => Ok(()),
+        names => Err(anyhow!(
+            "Project {} is defined more than once",
+            names.join(", ")
+        )),
+    }
+}
+
+fn merge_project(project: &mut toml::Table, layer: toml::Table) {
+    for (key, value) in layer {
+        match (key.as_str(), project.get_mut(&key), value) {
+            ("log" | "envs", Some(toml::Value::Table(table)), toml::Value::Table(layer)) => {
+                table.extend(layer)
+            }
+            (_, _, value) => {
+                project.insert(key, value);
+            }
+        }
+    }
+}
+
 // Scan root directories until we hopefully find the config file
-fn find_config_dir() -> Result<Option<PathBuf>, anyhow::Error> {
+pub fn find_config_dir() -> Result<Option<PathBuf>, anyhow::Error> {
     let mut dir = std::env::current_dir()?;
     loop {
         if dir.join(CONFIG_FILE).exists() {
@@ -0,0 +1,516 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    os::{
+        fd::AsRawFd,
+        unix::net::{UnixListener, UnixStream},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Project, RestartPolicy, RunningProject, WorkerConfig},
+    libc::{close_cloexec_fds, dup2, fork, setsid, try_waitpid, ExitStatus, Fork},
+    logs,
+};
+
+const TICK: Duration = Duration::from_millis(100);
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Clients send their request as soon as they connect
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(1);
+
+const DEFAULT_RESTART_BACKOFF_MS: u64 = 1000;
+const DEFAULT_RESTART_RESET_SECS: u64 = 60;
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Request sent from the cli to the daemon. One request per connection
+#[derive(Deserialize, Serialize, Debug)]
+pub enum Request {
+    Start(Vec<Project>),
+    Stop(Vec<String>),
+    Status,
+    Shutdown,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub enum Response {
+    Ok,
+    Status(DaemonStatus),
+    Error(String),
+}
+
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct DaemonStatus {
+    pub pid: i32,
+    pub projects: Vec<Supervised>,
+}
+
+/// A project spawned by the daemon, and what happened to the last run of it
+#[derive(Deserialize, Serialize, Clone, Debug)]
+pub struct Supervised {
+    pub project: Project,
+    pub pid: i32,
+    pub started_at: u64,
+    pub exited_at: Option<u64>,
+    pub exit_status: Option<ExitStatus>,
+    pub restarts: u32,
+    /// Set when the project is asked to stop, so it is not restarted when it exits
+    #[serde(skip)]
+    stopping: bool,
+    #[serde(skip)]
+    restart_at: Option<Instant>,
+}
+
+impl Supervised {
+    // Decide if and when the project should be restarted after it exited with `status`
+    fn schedule_restart(&mut self, status: ExitStatus) {
+        let restart = match self.project.restart.unwrap_or_default() {
+            RestartPolicy::Always => true,
+            RestartPolicy::OnFailure => !status.success(),
+            RestartPolicy::Never => false,
+        };
+
+        if !restart || self.stopping {
+            return;
+        }
+
+        // A project that ran for longer than the reset window is not in a crash loop
+        let reset = self
+            .project
+            .restart_reset_secs
+            .unwrap_or(DEFAULT_RESTART_RESET_SECS);
+        if now().saturating_sub(self.started_at) >= reset {
+            self.restarts = 0;
+        }
+
+        if let Some(max) = self.project.max_restarts {
+            if self.restarts >= max {
+                eprintln!("{} restarted {} times, giving up", self.project, max);
+                return;
+            }
+        }
+
+        let backoff = Duration::from_millis(
+            self.project
+                .restart_backoff_ms
+                .unwrap_or(DEFAULT_RESTART_BACKOFF_MS),
+        );
+        let backoff = backoff
+            .saturating_mul(2u32.saturating_pow(self.restarts))
+            .min(MAX_RESTART_BACKOFF);
+
+        self.restart_at = Some(Instant::now() + backoff);
+    }
+}
+
+pub struct Client {
+    stream: UnixStream,
+}
+
+impl Client {
+    /// Connect to the daemon for this config. Returns `None` if no daemon is running
+    pub fn connect(config: &WorkerConfig) -> Option<Self> {
+        let stream = UnixStream::connect(config.socket_file()).ok()?;
+        stream.set_read_timeout(Some(TIMEOUT)).ok()?;
+        Some(Self { stream })
+    }
+
+    pub fn send(mut self, request: Request) -> Result<Response, anyhow::Error> {
+        serde_json::to_writer(&mut self.stream, &request)?;
+        self.stream.write_all(b"\n")?;
+
+        let mut line = String::new();
+        BufReader::new(&self.stream).read_line(&mut line)?;
+
+        match serde_json::from_str(&line).context("Invalid response from daemon")? {
+            Response::Error(e) => Err(anyhow!(e)),
+            response => Ok(response),
+        }
+    }
+}
+
+/// Whether the request failed for not getting an answer from the daemon, e.g. since it is gone,
+/// rather than for the daemon answering with an error
+pub fn no_answer(e: &anyhow::Error) -> bool {
+    e.is::<std::io::Error>() || e.is::<serde_json::Error>()
+}
+
+struct Daemon {
+    config: WorkerConfig,
+    listener: UnixListener,
+    projects: HashMap<String, Supervised>,
+    /// When each scheduled task is to run next. `None` if its schedule never matches
+    next_runs: HashMap<String, Option<u64>>,
+    shutdown: bool,
+}
+
+impl Daemon {
+    fn handle(&mut self, stream: UnixStream) -> Result<(), anyhow::Error> {
+        // The loop supervising the projects waits while a client is handled, so a client that
+        // stops reading or writing is given up on
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+
+        // Connections without a request are only checking if the daemon is up
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let response = match serde_json::from_str(&line)? {
+            Request::Start(projects) => self.start(projects).map(|_| Response::Ok),
+            Request::Stop(names) => self.stop(names).map(|_| Response::Ok),
+            Request::Status => Ok(Response::Status(DaemonStatus {
+                pid: std::process::id() as i32,
+                projects: self.projects.values().cloned().collect(),
+            })),
+            Request::Shutdown => {
+                self.shutdown = true;
+                Ok(Response::Ok)
+            }
+        }
+        .unwrap_or_else(|e| Response::Error(e.to_string()));
+
+        let mut stream = stream;
+        serde_json::to_writer(&mut stream, &response)?;
+        stream.write_all(b"\n")?;
+
+        Ok(())
+    }
+
+    // The project may have been started since the cli checked, e.g. by a scheduled run while
+    // the request was waiting to be handled
+    fn start(&mut self, projects: Vec<Project>) -> Result<(), anyhow::Error> {
+        for project in projects {
+            if self.config.is_running(&project)? {
+                eprintln!("{} is already running", project);
+                continue;
+            }
+            self.spawn(project, 0, None)?;
+        }
+
+        Ok(())
+    }
+
+    fn spawn(
+        &mut self,
+        project: Project,
+        restarts: u32,
+        last_exit: Option<ExitStatus>,
+    ) -> Result<(), anyhow::Error> {
+        // Closed by the child once it is a session leader
+        let (mut ready, ready_writer) = std::io::pipe()?;
+        match fork().map_err(|_| anyhow!("Couldn't fork"))? {
+            Fork::Parent(pid) => {
+                // The child becomes a session leader, so the pid is also the session id. Wait
+                // for it to happen to not report the project as not running in the meantime
+                drop(ready_writer);
+                let _ = ready.read(&mut [0]);
+
+                let running = RunningProject {
+                    restarts,
+                    last_exit,
+                    ..RunningProject::new(project, pid)
+                };
+                self.config.store_state(&running)?;
+                self.config.record_start(&running.project, pid)?;
+                self.projects.insert(
+                    running.project.name.clone(),
+                    Supervised {
+                        project: running.project,
+                        pid,
+                        started_at: now(),
+                        exited_at: None,
+                        exit_status: None,
+                        restarts,
+                        stopping: false,
+                        restart_at: None,
+                    },
+                );
+                Ok(())
+            }
+            Fork::Child => {
+                setsid().expect("Couldn't setsid");
+                drop(ready_writer);
+                // The socket would keep clients waiting on a daemon that is gone, when the
+                // process in front of the project outlives it
+                close_cloexec_fds();
+                let err = self.config.exec(&project, false);
+                eprintln!("Couldn't start {}: {}", project.name, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    // The cli sends the signals to stop the projects. The daemon only needs to know that
+    // they should not be restarted when they exit
+    fn stop(&mut self, names: Vec<String>) -> Result<(), anyhow::Error> {
+        for supervised in self.projects.values_mut() {
+            if names.contains(&supervised.project.name) {
+                supervised.stopping = true;
+                supervised.restart_at = None;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Collect the exit status of every child that has exited since the last tick
+    fn reap(&mut self) {
+        while let Ok(Some((pid, status))) = try_waitpid() {
+            let Some(supervised) = self.projects.values_mut().find(|it| it.pid == pid) else {
+                continue;
+            };
+
+            eprintln!("{} {}", supervised.project, status);
+            let _ = self.config.remove_state(pid, &supervised.project);
+            let _ = self
+                .config
+                .record_exit(&supervised.project, pid, Some(status));
+            supervised.exited_at = Some(now());
+            supervised.exit_status = Some(status);
+            supervised.schedule_restart(status);
+        }
+    }
+
+    fn rotate_logs(&self) {
+        for supervised in self.projects.values() {
+            if supervised.exit_status.is_some() {
+                continue;
+            }
+
+            if let Err(e) = logs::rotate_if_too_large(&self.config, &supervised.project) {
+                eprintln!("Couldn't rotate log of {}: {}", supervised.project, e);
+            }
+        }
+    }
+
+    // Run the tasks whose schedule is due. A run is skipped if the last one is still going
+    fn run_scheduled(&mut self) {
+        let now = now();
+        let projects = self.config.projects.clone();
+        for project in projects {
+            let Some(schedule) = project.schedule.as_ref() else {
+                continue;
+            };
+            let next = self
+                .next_runs
+                .entry(project.name.clone())
+                .or_insert_with(|| schedule.next_after(now));
+            if next.is_none_or(|it| it > now) {
+                continue;
+            }
+            *next = schedule.next_after(now);
+
+            // The cli holds the lock while it waits for the daemon to start the project, so
+            // waiting for it here would keep both waiting
+            let _lock = match self.config.try_lock(&project) {
+                Ok(Some(lock)) => lock,
+                Ok(None) => {
+                    eprintln!("{} is being started, skipping scheduled run", project);
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Couldn't run scheduled {}: {}", project, e);
+                    continue;
+                }
+            };
+            match self.config.is_running(&project) {
+                Ok(true) => eprintln!("{} is still running, skipping scheduled run", project),
+                Ok(false) => {
+                    eprintln!("Running scheduled {}", project);
+                    let name = project.name.clone();
+                    if let Err(e) = self.spawn(project, 0, None) {
+                        eprintln!("Couldn't run scheduled {}: {}", name, e);
+                    }
+                }
+                Err(e) => eprintln!("Couldn't run scheduled {}: {}", project, e),
+            }
+        }
+    }
+
+    fn restart_due(&mut self) {
+        let now = Instant::now();
+        let due: Vec<_> = self
+            .projects
+            .values()
+            .filter(|it| it.restart_at.is_some_and(|at| at <= now))
+            .cloned()
+            .collect();
+
+        for supervised in due {
+            let name = supervised.project.name.clone();
+            // The project may have been started by someone else while waiting to restart it
+            let _lock = match self.config.try_lock(&supervised.project) {
+                Ok(Some(lock)) => lock,
+                Ok(None) => continue,
+                Err(e) => {
+                    eprintln!("Couldn't restart {}: {}", name, e);
+                    self.projects.remove(&name);
+                    continue;
+                }
+            };
+            match self.config.is_running(&supervised.project) {
+                Ok(false) => {}
+                Ok(true) => {
+                    eprintln!(
+                        "{} is already running, not restarting it",
+                        supervised.project
+                    );
+                    if let Some(it) = self.projects.get_mut(&name) {
+                        it.restart_at = None;
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("Couldn't restart {}: {}", name, e);
+                    self.projects.remove(&name);
+                    continue;
+                }
+            }
+
+            eprintln!("Restarting {}", supervised.project);
+            let restarts = supervised.restarts + 1;
+            if let Err(e) = self.spawn(supervised.project, restarts, supervised.exit_status) {
+                eprintln!("Couldn't restart {}: {}", name, e);
+                self.projects.remove(&name);
+            }
+        }
+    }
+}
+
+/// Run the daemon in the current process until it is asked to shut down
+pub fn run(config: WorkerConfig) -> Result<(), anyhow::Error> {
+    if Client::connect(&config).is_some() {
+        return Err(anyhow!("Daemon is already running"));
+    }
+
+    // A socket file without a daemon listening on it is left over from a daemon that died
+    let socket = config.socket_file();
+    let _ = std::fs::remove_file(&socket);
+
+    let listener = UnixListener::bind(&socket)?;
+    listener.set_nonblocking(true)?;
+
+    let mut daemon = Daemon {
+        config,
+        listener,
+        projects: HashMap::new(),
+        next_runs: HashMap::new(),
+        shutdown: false,
+    };
+
+    while !daemon.shutdown {
+        match daemon.listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(e) = daemon.handle(stream) {
+                    eprintln!("Error handling request: {}", e);
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(TICK),
+            Err(e) => return Err(e.into()),
+        }
+
+        daemon.reap();
+        daemon.restart_due();
+        daemon.run_scheduled();
+        daemon.rotate_logs();
+    }
+
+    std::fs::remove_file(&socket)?;
+
+    Ok(())
+}
+
+/// Fork a daemon into the background and wait until it accepts connections
+pub fn start(config: WorkerConfig) -> Result<(), anyhow::Error> {
+    if Client::connect(&config).is_some() {
+        return Err(anyhow!("Daemon is already running"));
+    }
+
+    match fork().map_err(|_| anyhow!("Couldn't fork"))? {
+        Fork::Parent(_) => {
+            let start = Instant::now();
+            while Instant::now().duration_since(start) < TIMEOUT {
+                if Client::connect(&config).is_some() {
+                    return Ok(());
+                }
+                std::thread::sleep(TICK);
+            }
+
+            Err(anyhow!(
+                "Daemon did not start. See {}",
+                config.daemon_log_file().display()
+            ))
+        }
+        Fork::Child => {
+            setsid().expect("Couldn't setsid");
+
+            // Detach from the terminal of the cli
+            let log = OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(config.daemon_log_file())?;
+            let null = File::open("/dev/null")?;
+            dup2(null.as_raw_fd(), 0).map_err(|_| anyhow!("Couldn't redirect stdin"))?;
+            dup2(log.as_raw_fd(), 1).map_err(|_| anyhow!("Couldn't redirect stdout"))?;
+            dup2(log.as_raw_fd(), 2).map_err(|_| anyhow!("Couldn't redirect stderr"))?;
+
+            if let Err(e) = run(config) {
+                eprintln!("Daemon exited with error: {}", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+    }
+}
+
+pub fn stop(config: &WorkerConfig) -> Result<(), anyhow::Error> {
+    let client = Client::connect(config).context("Daemon is not running")?;
+    client.send(Request::Shutdown)?;
+
+    let start = Instant::now();
+    while Instant::now().duration_since(start) < TIMEOUT {
+        if Client::connect(config).is_none() {
+            return Ok(());
+        }
+        std::thread::sleep(TICK);
+    }
+
+    Err(anyhow!("Daemon did not shut down"))
+}
+
+pub fn status(config: &WorkerConfig) -> Result<(), anyhow::Error> {
+    let client = Client::connect(config).context("Daemon is not running")?;
+    let Response::Status(status) = client.send(Request::Status)? else {
+        return Err(anyhow!("Invalid response from daemon"));
+    };
+
+    println!("Daemon is running with pid {}", status.pid);
+    for supervised in status.projects {
+        match supervised.exit_status {
+            Some(exit_status) => print!("{} {}", supervised.project, exit_status),
+            None => print!("{} is running", supervised.project),
+        }
+        match supervised.restarts {
+            0 => println!(),
+            restarts => println!(" (restarts: {})", restarts),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs())
+        .unwrap_or_default()
+}
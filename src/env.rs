@@ -0,0 +1,103 @@
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Context;
+
+/// Read the variables in a `.env` file. Lines are `KEY=value`, optionally prefixed with
+/// `export`. Values can be quoted, and unquoted or double quoted values are interpolated with
+/// `vars` and the variables before them in the file. Variables that are not set are added to
+/// `unset`
+pub fn read_env_file(
+    path: &Path,
+    vars: &HashMap<String, String>,
+    unset: &mut Vec<String>,
+) -> Result<Vec<(String, String)>, anyhow::Error> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read env file {}", path.display()))?;
+
+    let mut vars = vars.clone();
+    let mut parsed = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line.split_once('=').with_context(|| {
+            format!("Expected KEY=value on line {} of {}", i + 1, path.display())
+        })?;
+        let key = key.trim().to_string();
+        let value = value.trim();
+
+        let value = if let Some(value) = quoted(value, '\'') {
+            value.to_string()
+        } else if let Some(value) = quoted(value, '"') {
+            let value = value.replace("\\n", "\n").replace("\\\"", "\"");
+            interpolate(&value, &vars, unset)?
+        } else {
+            // Comments can follow unquoted values
+            let value = value.split(" #").next().unwrap_or_default().trim_end();
+            interpolate(value, &vars, unset)?
+        };
+
+        vars.insert(key.clone(), value.clone());
+        parsed.push((key, value));
+    }
+
+    Ok(parsed)
+}
+
+fn quoted(value: &str, quote: char) -> Option<&str> {
+    value.strip_prefix(quote)?.strip_suffix(quote)
+}
+
+/// Replace `${VAR}` with the value of `VAR`, and `${VAR:-default}` with `default` if `VAR` is not
+/// set or empty. `$${` is left as `${`. `${VAR}` is left as it is if `VAR` is not set, as it may
+/// be meant for a shell, and `VAR` is added to `unset`
+pub fn interpolate(
+    value: &str,
+    vars: &HashMap<String, String>,
+    unset: &mut Vec<String>,
+) -> Result<String, anyhow::Error> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(i) = rest.find('$') {
+        result.push_str(&rest[..i]);
+        rest = &rest[i..];
+
+        if let Some(escaped) = rest.strip_prefix("$${") {
+            result.push_str("${");
+            rest = escaped;
+            continue;
+        }
+
+        let Some(expression) = rest.strip_prefix("${") else {
+            result.push('$');
+            rest = &rest[1..];
+            continue;
+        };
+        let end = expression
+            .find('}')
+            .with_context(|| format!("Missing }} in {}", value))?;
+
+        let (name, default) = match expression[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&expression[..end], None),
+        };
+        let replacement = match (vars.get(name), default) {
+            (Some(var), Some(default)) if var.is_empty() => default,
+            (Some(var), _) => var.as_str(),
+            (None, Some(default)) => default,
+            (None, None) => {
+                unset.push(name.to_string());
+                &rest[..end + 3]
+            }
+        };
+        result.push_str(replacement);
+        rest = &expression[end + 1..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
@@ -0,0 +1,365 @@
+use std::{collections::HashSet, time::SystemTime};
+
+use serde::Serialize;
+
+use crate::{
+    config::{LastRun, Project, ProjectKind, RunningProject},
+    daemon::now,
+    health::Health,
+    libc::{ExitStatus, ProcessInfo},
+    logs::{LogLine, Stream},
+};
+
+/// Output format of the commands printing projects or logs
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable text
+    #[default]
+    Text,
+    Json,
+    Yaml,
+    /// Aligned columns with a header
+    Table,
+}
+
+/// A project as printed by `list`. Kept separate from `Project` to keep the schema stable when
+/// options are added to the config
+#[derive(Serialize, Debug)]
+pub struct ProjectOutput {
+    pub name: String,
+    pub display: Option<String>,
+    pub command: String,
+    pub cwd: String,
+    pub groups: Vec<String>,
+    pub depends_on: Vec<String>,
+    pub kind: ProjectKind,
+    pub schedule: Option<String>,
+}
+
+impl From<&Project> for ProjectOutput {
+    fn from(project: &Project) -> Self {
+        Self {
+            name: project.name.clone(),
+            display: project.display.clone(),
+            command: project.command.clone(),
+            cwd: project.cwd.clone(),
+            groups: project.group.clone().unwrap_or_default(),
+            depends_on: project.depends_on.clone().unwrap_or_default(),
+            kind: project.kind,
+            schedule: project.schedule.as_ref().map(|it| it.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ProjectOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ref display) = self.display {
+            write!(f, "{} ({})", display, self.name)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Running,
+    /// Stopped with `worker stop`, or never started
+    Stopped,
+    /// Exited by itself
+    Exited,
+    /// Killed by a signal not sent by `worker stop`
+    Crashed,
+}
+
+impl std::fmt::Display for State {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            State::Running => write!(f, "running"),
+            State::Stopped => write!(f, "stopped"),
+            State::Exited => write!(f, "exited"),
+            State::Crashed => write!(f, "crashed"),
+        }
+    }
+}
+
+/// A project as printed by `status --all`
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum ProjectStatus {
+    Running(StatusOutput),
+    Stopped(StoppedOutput),
+}
+
+impl ProjectStatus {
+    pub fn project(&self) -> &ProjectOutput {
+        match self {
+            ProjectStatus::Running(it) => &it.project,
+            ProjectStatus::Stopped(it) => &it.project,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        match self {
+            ProjectStatus::Running(it) => it.state,
+            ProjectStatus::Stopped(it) => it.state,
+        }
+    }
+}
+
+/// A project that is not running, with how its last run ended
+#[derive(Serialize, Debug)]
+pub struct StoppedOutput {
+    #[serde(flatten)]
+    pub project: ProjectOutput,
+    pub state: State,
+    pub started_at: Option<u64>,
+    pub stopped_at: Option<u64>,
+    pub exit: Option<ExitStatus>,
+    pub stopped_by: Option<String>,
+    /// How long the last run took, if it ran to completion
+    pub duration_secs: Option<u64>,
+    /// When the daemon runs the task next, for scheduled tasks
+    pub next_run: Option<u64>,
+}
+
+impl StoppedOutput {
+    pub fn new(project: &Project, run: Option<LastRun>) -> Self {
+        let run = run.unwrap_or_default();
+        let state = match run.exit {
+            _ if run.stopped_by.is_some() => State::Stopped,
+            Some(ExitStatus::Code(_)) => State::Exited,
+            Some(ExitStatus::Signal(_)) => State::Crashed,
+            None => State::Stopped,
+        };
+
+        Self {
+            project: project.into(),
+            state,
+            started_at: (run.started_at > 0).then_some(run.started_at),
+            stopped_at: run.stopped_at,
+            exit: run.exit,
+            stopped_by: run.stopped_by,
+            duration_secs: run
+                .exit
+                .and(run.stopped_at)
+                .map(|it| it.saturating_sub(run.started_at)),
+            next_run: project
+                .schedule
+                .as_ref()
+                .and_then(|it| it.next_after(now())),
+        }
+    }
+}
+
+/// A running project as printed by `status`. The cpu usage and memory are summed over every
+/// process in the session of the project
+#[derive(Serialize, Debug)]
+pub struct StatusOutput {
+    #[serde(flatten)]
+    pub project: ProjectOutput,
+    pub state: State,
+    pub pid: i32,
+    pub sid: i32,
+    pub started_at: u64,
+    /// The command as it was run, which can differ from the config if it changed since
+    pub argv: Vec<String>,
+    pub uptime_secs: Option<u64>,
+    pub health: Option<Health>,
+    pub restarts: u32,
+    pub last_exit: Option<ExitStatus>,
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+    pub children: usize,
+    pub processes: Vec<ProcessInfo>,
+}
+
+impl StatusOutput {
+    pub fn new(
+        project: &RunningProject,
+        uptime_secs: Option<u64>,
+        health: Option<Health>,
+        processes: Vec<ProcessInfo>,
+    ) -> Self {
+        Self {
+            project: (&project.project).into(),
+            state: State::Running,
+            pid: project.pid,
+            sid: project.sid(),
+            started_at: project.started_at_ms / 1000,
+            argv: project.argv.clone(),
+            uptime_secs,
+            health,
+            restarts: project.restarts,
+            last_exit: project.last_exit,
+            cpu_percent: processes.iter().map(|it| it.cpu_percent).sum(),
+            rss_bytes: processes.iter().map(|it| it.rss_bytes).sum(),
+            children: processes.len() - roots(&processes).len(),
+            processes,
+        }
+    }
+}
+
+/// A line printed by `logs`
+#[derive(Serialize, Debug)]
+pub struct LogOutput {
+    pub project: String,
+    pub time: Option<String>,
+    pub stream: Option<Stream>,
+    pub line: String,
+}
+
+impl LogOutput {
+    pub fn new(project: &str, line: LogLine) -> Self {
+        Self {
+            project: project.to_string(),
+            time: line.time.map(format_time),
+            stream: line.stream,
+            line: line.text,
+        }
+    }
+}
+
+pub fn format_time(time: SystemTime) -> String {
+    humantime::format_rfc3339_millis(time).to_string()
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+// Processes without a parent in the session, e.g. when the session leader has exited
+fn roots(processes: &[ProcessInfo]) -> Vec<&ProcessInfo> {
+    let pids: HashSet<_> = processes.iter().map(|it| it.pid).collect();
+    processes
+        .iter()
+        .filter(|it| it.parent.is_none_or(|parent| !pids.contains(&parent)))
+        .collect()
+}
+
+/// The process and its descendants, in the order of the processes
+pub fn descendants(processes: &[ProcessInfo], pid: i32) -> Vec<ProcessInfo> {
+    let mut pids = HashSet::from([pid]);
+    let mut found = true;
+    while found {
+        found = false;
+        for process in processes {
+            if !pids.contains(&process.pid) && process.parent.is_some_and(|it| pids.contains(&it)) {
+                pids.insert(process.pid);
+                found = true;
+            }
+        }
+    }
+
+    processes
+        .iter()
+        .filter(|it| pids.contains(&it.pid))
+        .cloned()
+        .collect()
+}
+
+/// Draw the processes as a tree, one line per process
+pub fn tree(processes: &[ProcessInfo]) -> Vec<String> {
+    let roots = roots(processes);
+    let mut lines = Vec::new();
+    for (i, root) in roots.iter().enumerate() {
+        draw(processes, root, "", i == roots.len() - 1, &mut lines);
+    }
+
+    lines
+}
+
+fn draw(
+    processes: &[ProcessInfo],
+    process: &ProcessInfo,
+    indent: &str,
+    last: bool,
+    lines: &mut Vec<String>,
+) {
+    let (branch, next) = if last {
+        ("└─ ", "   ")
+    } else {
+        ("├─ ", "│  ")
+    };
+    lines.push(format!(
+        "{}{}{} {} ({})",
+        indent, branch, process.pid, process.command, process.state
+    ));
+
+    let children: Vec<_> = processes
+        .iter()
+        .filter(|it| it.parent == Some(process.pid))
+        .collect();
+    for (i, child) in children.iter().enumerate() {
+        let indent = format!("{}{}", indent, next);
+        draw(processes, child, &indent, i == children.len() - 1, lines);
+    }
+}
+
+/// Serialize every item as one document, or return `None` for the formats that are not
+/// serialized
+pub fn serialize<T: Serialize>(
+    format: Format,
+    items: &[T],
+) -> Result<Option<String>, anyhow::Error> {
+    Ok(match format {
+        Format::Json => Some(serde_json::to_string_pretty(items)?),
+        Format::Yaml => Some(serde_yaml::to_string(items)?.trim_end().to_string()),
+        Format::Text | Format::Table => None,
+    })
+}
+
+/// Serialize a single item on one line, for output that is streamed, e.g. followed logs
+pub fn serialize_line<T: Serialize>(
+    format: Format,
+    item: &T,
+) -> Result<Option<String>, anyhow::Error> {
+    Ok(match format {
+        Format::Json => Some(serde_json::to_string(item)?),
+        Format::Yaml => Some(format!("---\n{}", serde_yaml::to_string(item)?.trim_end())),
+        Format::Text | Format::Table => None,
+    })
+}
+
+/// Align the rows in columns under the header. The last column is not padded
+pub fn table(header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|it| it.len()).collect();
+    for row in rows.iter() {
+        for (i, cell) in row.iter().enumerate() {
+            widths[i] = widths[i].max(cell.chars().count());
+        }
+    }
+
+    let header = header.iter().map(|it| it.to_string()).collect();
+    std::iter::once(header)
+        .chain(rows)
+        .map(|row| {
+            row.iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    if i == widths.len() - 1 {
+                        cell.to_string()
+                    } else {
+                        format!("{:width$}", cell, width = widths[i])
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
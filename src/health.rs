@@ -0,0 +1,247 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    process::Stdio,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    config::{HealthCheck, Project, RunningProject, WorkerConfig},
+    logs::{LogFilter, LogReader},
+};
+
+const DEFAULT_INTERVAL_MS: u64 = 500;
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+
+const FINISHED_INTERVAL: Duration = Duration::from_millis(100);
+
+// A single check should never block for longer than this
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Health {
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+impl std::fmt::Display for Health {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Health::Starting => write!(f, "starting"),
+            Health::Healthy => write!(f, "healthy"),
+            Health::Unhealthy => write!(f, "unhealthy"),
+        }
+    }
+}
+
+impl HealthCheck {
+    fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.unwrap_or(DEFAULT_INTERVAL_MS))
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs.unwrap_or(DEFAULT_TIMEOUT_SECS))
+    }
+}
+
+/// Runs the healthcheck of a project, once or again and again. The log is read from where the
+/// last check left off, and is healthy from the first line matching
+struct Checker<'a> {
+    project: &'a Project,
+    healthcheck: &'a HealthCheck,
+    reader: LogReader,
+    logged: bool,
+}
+
+impl<'a> Checker<'a> {
+    fn new(config: &WorkerConfig, project: &'a Project, healthcheck: &'a HealthCheck) -> Self {
+        Self {
+            project,
+            healthcheck,
+            reader: LogReader::new(config.log_file(project)),
+            logged: false,
+        }
+    }
+
+    fn check(&mut self) -> bool {
+        let healthcheck = self.healthcheck;
+        let res = if let Some(ref address) = healthcheck.tcp {
+            check_tcp(address)
+        } else if let Some(ref url) = healthcheck.http {
+            check_http(url, healthcheck.status)
+        } else if let Some(ref command) = healthcheck.command {
+            check_command(self.project, command)
+        } else if let Some(ref log) = healthcheck.log {
+            self.check_log(log)
+        } else {
+            Ok(false)
+        };
+
+        res.unwrap_or(false)
+    }
+
+    fn check_log(&mut self, log: &str) -> Result<bool, anyhow::Error> {
+        if !self.logged {
+            let regex = Regex::new(log)?;
+            // The reader strips the time and stream of captured lines
+            self.logged = self
+                .reader
+                .poll(&LogFilter::default())?
+                .iter()
+                .any(|line| regex.is_match(&line.text));
+        }
+
+        Ok(self.logged)
+    }
+}
+
+/// Health of a running project. `None` if the project has no healthcheck
+pub fn health(config: &WorkerConfig, project: &RunningProject) -> Option<Health> {
+    let healthcheck = project.project.healthcheck.as_ref()?;
+
+    if Checker::new(config, &project.project, healthcheck).check() {
+        Some(Health::Healthy)
+    } else if config
+        .uptime(project)
+        .is_some_and(|uptime| uptime < healthcheck.timeout())
+    {
+        Some(Health::Starting)
+    } else {
+        Some(Health::Unhealthy)
+    }
+}
+
+/// Block until the project is healthy. Projects without a healthcheck are ready as soon as
+/// they are running
+pub fn wait_until_healthy(config: &WorkerConfig, project: &Project) -> Result<(), anyhow::Error> {
+    let healthcheck = project.healthcheck.clone().unwrap_or_default();
+    let mut checker = Checker::new(config, project, &healthcheck);
+    let start = Instant::now();
+
+    loop {
+        if !config.is_running(project)? {
+            return Err(anyhow!("{} exited before it was ready", project));
+        }
+
+        if project.healthcheck.is_none() || checker.check() {
+            return Ok(());
+        }
+
+        if Instant::now().duration_since(start) > healthcheck.timeout() {
+            return Err(anyhow!(
+                "{} was not healthy after {} seconds",
+                project,
+                healthcheck.timeout().as_secs()
+            ));
+        }
+
+        std::thread::sleep(healthcheck.interval());
+    }
+}
+
+/// Wait for a task to run to completion. Fails if it did not exit successfully
+pub fn wait_until_finished(config: &WorkerConfig, project: &Project) -> Result<(), anyhow::Error> {
+    while config.is_running(project)? {
+        std::thread::sleep(FINISHED_INTERVAL);
+    }
+
+    // The daemon records the exit a moment after the task is gone
+    let start = Instant::now();
+    loop {
+        match config.last_run(project).and_then(|it| it.exit) {
+            Some(exit) if exit.success() => return Ok(()),
+            Some(exit) => return Err(anyhow!("{} {}", project, exit)),
+            None if start.elapsed() > CHECK_TIMEOUT => {
+                return Err(anyhow!("{} stopped without an exit code", project))
+            }
+            None => std::thread::sleep(FINISHED_INTERVAL),
+        }
+    }
+}
+
+fn check_tcp(address: &str) -> Result<bool, anyhow::Error> {
+    for address in address.to_socket_addrs()? {
+        if TcpStream::connect_timeout(&address, CHECK_TIMEOUT).is_ok() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+fn check_http(url: &str, status: Option<u16>) -> Result<bool, anyhow::Error> {
+    let rest = url
+        .strip_prefix("http://")
+        .context("Only http:// is supported")?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .context("Couldn't resolve host")?;
+    let mut stream = TcpStream::connect_timeout(&address, CHECK_TIMEOUT)?;
+    stream.set_read_timeout(Some(CHECK_TIMEOUT))?;
+    stream.set_write_timeout(Some(CHECK_TIMEOUT))?;
+
+    write!(
+        stream,
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, host
+    )?;
+
+    // Only the status line is interesting, e.g. `HTTP/1.1 200 OK`
+    let mut buf = [0; 64];
+    let n = stream.read(&mut buf)?;
+    let response = String::from_utf8_lossy(&buf[..n]);
+    let code: u16 = response
+        .split_whitespace()
+        .nth(1)
+        .context("Invalid http response")?
+        .parse()?;
+
+    Ok(match status {
+        Some(status) => code == status,
+        None => (200..300).contains(&code),
+    })
+}
+
+fn check_command(project: &Project, command: &str) -> Result<bool, anyhow::Error> {
+    let parts = shlex::split(command).context(format!("Couldn't parse command: {}", command))?;
+    let program = parts.first().context("Empty healthcheck command")?;
+
+    let mut child = std::process::Command::new(program)
+        .args(&parts[1..])
+        .envs(project.envs.clone().unwrap_or_default())
+        .current_dir(&project.cwd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let start = Instant::now();
+    while Instant::now().duration_since(start) < CHECK_TIMEOUT {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status.success());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    child.kill()?;
+    child.wait()?;
+
+    Ok(false)
+}
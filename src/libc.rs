@@ -1,5 +1,14 @@
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    os::fd::OwnedFd,
+    path::Path,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use anyhow::anyhow;
 use serde::{Deserialize, Serialize};
-use sysinfo::System;
+use sysinfo::{ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, System, UpdateKind};
 
 pub enum Fork {
     Parent(libc::pid_t),
@@ -33,55 +42,592 @@ pub fn waitpid(pid: i32) -> Result<libc::pid_t, i32> {
     }
 }
 
+pub fn getsid(pid: libc::pid_t) -> Result<libc::pid_t, i32> {
+    match unsafe { libc::getsid(pid) } {
+        -1 => Err(-1),
+        res => Ok(res),
+    }
+}
+
+/// Reap any child that has exited without blocking. Returns `None` if no child is ready
+pub fn try_waitpid() -> Result<Option<(libc::pid_t, ExitStatus)>, i32> {
+    let mut status: i32 = 0;
+    let res = unsafe { libc::waitpid(-1, &mut status, libc::WNOHANG) };
+
+    match res {
+        -1 => Err(-1),
+        0 => Ok(None),
+        pid => Ok(Some((pid, ExitStatus::from_raw(status)))),
+    }
+}
+
+pub fn dup2(src: i32, dst: i32) -> Result<(), i32> {
+    match unsafe { libc::dup2(src, dst) } {
+        -1 => Err(-1),
+        _ => Ok(()),
+    }
+}
+
+/// Create a named pipe at the path, readable and writable by the user only
+pub fn mkfifo(path: &Path) -> Result<(), i32> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|_| -1)?;
+    match unsafe { libc::mkfifo(path.as_ptr(), 0o600) } {
+        -1 => Err(-1),
+        _ => Ok(()),
+    }
+}
+
+/// Open a pseudo-terminal with the window size. Returns the side read by whoever is in front of
+/// the terminal, and the side a program runs in. Input is not echoed and newlines are written as
+/// they are, to keep the output the way the program wrote it
+pub fn openpty(rows: u16, columns: u16) -> Result<(OwnedFd, OwnedFd), i32> {
+    use std::os::fd::{AsRawFd, FromRawFd};
+
+    let master = match unsafe { libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY) } {
+        -1 => return Err(-1),
+        fd => unsafe { OwnedFd::from_raw_fd(fd) },
+    };
+    let fd = master.as_raw_fd();
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1
+        || unsafe { libc::grantpt(fd) } == -1
+        || unsafe { libc::unlockpt(fd) } == -1
+    {
+        return Err(-1);
+    }
+
+    let name = unsafe { libc::ptsname(fd) };
+    if name.is_null() {
+        return Err(-1);
+    }
+    let slave = match unsafe { libc::open(name, libc::O_RDWR | libc::O_NOCTTY | libc::O_CLOEXEC) } {
+        -1 => return Err(-1),
+        fd => unsafe { OwnedFd::from_raw_fd(fd) },
+    };
+
+    let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(slave.as_raw_fd(), &mut termios) } == -1 {
+        return Err(-1);
+    }
+    termios.c_lflag &= !libc::ECHO;
+    termios.c_oflag &= !libc::ONLCR;
+    if unsafe { libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) } == -1 {
+        return Err(-1);
+    }
+
+    let size = libc::winsize {
+        ws_row: rows,
+        ws_col: columns,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    if unsafe { libc::ioctl(slave.as_raw_fd(), libc::TIOCSWINSZ, &size) } == -1 {
+        return Err(-1);
+    }
+
+    Ok((master, slave))
+}
+
+/// Close the file descriptors marked to be closed on exec, like sockets, locks and files opened
+/// by the current process. For a forked child that doesn't exec, and would keep them open
+pub fn close_cloexec_fds() {
+    let dir = match Path::new("/proc/self/fd").exists() {
+        true => "/proc/self/fd",
+        false => "/dev/fd",
+    };
+    // Collected first, since reading the directory opens a descriptor as well
+    let fds: Vec<i32> = std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .collect();
+
+    for fd in fds.into_iter().filter(|it| *it > 2) {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+        if flags != -1 && flags & libc::FD_CLOEXEC != 0 {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+/// Send the signal to the process and its descendants, for processes that don't have a
+/// session of their own to signal
+pub fn signal_tree(pid: i32, signal: &Signal) -> Result<(), i32> {
+    let mut sys = System::new();
+    sys.refresh_processes(ProcessesToUpdate::All, true);
+    let mut pids = vec![pid];
+    let mut i = 0;
+    while i < pids.len() {
+        let parent = sysinfo::Pid::from_u32(pids[i] as u32);
+        pids.extend(
+            sys.processes()
+                .values()
+                .filter(|it| it.parent() == Some(parent))
+                .map(|it| it.pid().as_u32() as i32),
+        );
+        i += 1;
+    }
+
+    // Only the process itself has to exist, the descendants may have exited in the meantime
+    for (i, pid) in pids.into_iter().enumerate() {
+        match unsafe { libc::kill(pid, signal.as_raw()) } {
+            0 => {}
+            e if i == 0 => return Err(e),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 pub fn stop_pg(sid: i32, signal: &Signal) -> Result<(), i32> {
-    match unsafe { libc::killpg(sid, signal.to_owned() as i32) } {
+    match unsafe { libc::killpg(sid, signal.as_raw()) } {
         0 => Ok(()),
         e => Err(e),
     }
 }
 
+/// Ignore the signals used to stop or reload projects. SIGKILL can't be ignored
+const STOP_SIGNALS: [Signal; 7] = [
+    Signal::SIGHUP,
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTERM,
+    Signal::SIGUSR1,
+    Signal::SIGUSR2,
+    Signal::SIGALRM,
+];
+
+pub fn ignore_stop_signals() {
+    for signal in STOP_SIGNALS {
+        ignore_signal(signal);
+    }
+}
+
+/// Undo `ignore_stop_signals`, as ignored signals stay ignored through exec. Only calls
+/// `signal`, so it can be used between fork and exec
+pub fn reset_stop_signals() {
+    for signal in STOP_SIGNALS {
+        unsafe { libc::signal(signal.as_raw(), libc::SIG_DFL) };
+    }
+}
+
+pub fn ignore_signal(signal: Signal) {
+    unsafe { libc::signal(signal.as_raw(), libc::SIG_IGN) };
+}
+
+// The process signals are forwarded to. Read by the signal handler, so it can't take a lock
+static FORWARD_TO: AtomicI32 = AtomicI32::new(0);
+
+extern "C" fn forward(signal: libc::c_int) {
+    let pid = FORWARD_TO.load(Ordering::Relaxed);
+    if pid > 0 {
+        unsafe { libc::kill(pid, signal) };
+    }
+}
+
+/// Set the process to forward the signals to
+pub fn forward_to(pid: libc::pid_t) {
+    FORWARD_TO.store(pid, Ordering::Relaxed);
+}
+
+/// Send the signals received by the current process on to the process set with `forward_to`.
+/// They are dropped until it is set. Child processes get the default handlers back when they
+/// exec, unlike ignored signals
+pub fn forward_signals(signals: &[Signal]) {
+    for signal in signals {
+        unsafe { libc::signal(signal.as_raw(), forward as *const () as libc::sighandler_t) };
+    }
+}
+
+/// Terminate the current process with the signal, even if it was ignored
+pub fn raise(signal: i32) {
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+/// Create an inotify instance that is read without blocking
+#[cfg(target_os = "linux")]
+pub fn inotify_init() -> Result<OwnedFd, i32> {
+    use std::os::fd::FromRawFd;
+
+    match unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) } {
+        -1 => Err(-1),
+        fd => Ok(unsafe { OwnedFd::from_raw_fd(fd) }),
+    }
+}
+
+/// Watch a file, or the files directly in a directory, for changes to their content or names.
+/// Returns the watch descriptor the events of the path are reported with
+#[cfg(target_os = "linux")]
+pub fn inotify_add_watch(inotify: &OwnedFd, path: &Path) -> Result<i32, i32> {
+    use std::os::{fd::AsRawFd, unix::ffi::OsStrExt};
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).map_err(|_| -1)?;
+    let mask = libc::IN_MODIFY
+        | libc::IN_ATTRIB
+        | libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO;
+    match unsafe { libc::inotify_add_watch(inotify.as_raw_fd(), path.as_ptr(), mask) } {
+        -1 => Err(-1),
+        wd => Ok(wd),
+    }
+}
+
+/// A change reported by inotify
+#[derive(Debug)]
+pub struct InotifyEvent {
+    pub wd: i32,
+    /// Name of the file in the watched directory. Empty when the watched path itself changed
+    pub name: OsString,
+    /// A directory was created or moved into the watched directory
+    pub new_dir: bool,
+    /// The watch was removed, e.g. since the path was deleted
+    pub removed: bool,
+}
+
+/// Read the events that are ready, without blocking
+#[cfg(target_os = "linux")]
+pub fn inotify_read(inotify: &OwnedFd) -> Vec<InotifyEvent> {
+    use std::os::{fd::AsRawFd, unix::ffi::OsStrExt};
+
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+    let mut events = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = unsafe { libc::read(inotify.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            return events;
+        }
+
+        let mut offset = 0;
+        while offset + HEADER <= n as usize {
+            let event: libc::inotify_event =
+                unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr().cast()) };
+            let name = &buf[offset + HEADER..offset + HEADER + event.len as usize];
+            // The name is padded with nul bytes
+            let name = name.split(|it| *it == 0).next().unwrap_or_default();
+
+            events.push(InotifyEvent {
+                wd: event.wd,
+                name: std::ffi::OsStr::from_bytes(name).to_os_string(),
+                new_dir: event.mask & libc::IN_ISDIR != 0
+                    && event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0,
+                removed: event.mask & libc::IN_IGNORED != 0,
+            });
+            offset += HEADER + event.len as usize;
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn inotify_init() -> Result<OwnedFd, i32> {
+    Err(-1)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn inotify_add_watch(_inotify: &OwnedFd, _path: &Path) -> Result<i32, i32> {
+    Err(-1)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn inotify_read(_inotify: &OwnedFd) -> Vec<InotifyEvent> {
+    Vec::new()
+}
+
+/// A time broken down in the local time zone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1 to 12
+    pub month: i32,
+    /// 1 to 31
+    pub day: i32,
+    pub hour: i32,
+    pub minute: i32,
+    /// 0 to 6, starting with Sunday
+    pub weekday: i32,
+}
+
+pub fn localtime(time: i64) -> Option<LocalTime> {
+    let time = time as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return None;
+    }
+
+    Some(LocalTime {
+        year: tm.tm_year + 1900,
+        month: tm.tm_mon + 1,
+        day: tm.tm_mday,
+        hour: tm.tm_hour,
+        minute: tm.tm_min,
+        weekday: tm.tm_wday,
+    })
+}
+
+/// The unix time of a local time. Fields out of range carry over, e.g. month 13 is January of
+/// the next year. The weekday is ignored
+pub fn mktime(time: &LocalTime) -> Option<libc::time_t> {
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    tm.tm_year = time.year - 1900;
+    tm.tm_mon = time.month - 1;
+    tm.tm_mday = time.day;
+    tm.tm_hour = time.hour;
+    tm.tm_min = time.minute;
+    // Let the time zone decide if daylight saving time is in effect
+    tm.tm_isdst = -1;
+
+    match unsafe { libc::mktime(&mut tm) } {
+        -1 => None,
+        time => Some(time),
+    }
+}
+
+/// Whether the process exists, and has not exited
+pub fn is_process_running(pid: libc::pid_t) -> bool {
+    let mut sys = System::new();
+    let pid = sysinfo::Pid::from_u32(pid as u32);
+    sys.refresh_processes(ProcessesToUpdate::Some(&[pid]), true);
+    sys.process(pid)
+        .is_some_and(|it| it.status() != ProcessStatus::Zombie)
+}
+
 pub fn has_processes_running(sid: libc::pid_t) -> bool {
     let mut sys = System::new();
     sys.refresh_all();
+    // Zombies have exited, and are only waiting for their parent to reap them
     sys.processes().iter().any(|(_, p)| {
-        p.session_id()
-            .is_some_and(|session_id| session_id.as_u32() == sid as u32)
+        p.status() != ProcessStatus::Zombie
+            && p.session_id()
+                .is_some_and(|session_id| session_id.as_u32() == sid as u32)
     })
 }
 
-#[derive(Deserialize, Clone, Debug, Serialize, Hash, PartialEq, Eq)]
-#[non_exhaustive]
-#[repr(i32)]
-pub enum Signal {
-    SIGHUP = 1,
-    SIGINT = 2,
-    SIGQUIT = 3,
-    SIGILL = 4,
-    SIGTRAP = 5,
-    SIGABRT = 6,
-    SIGBUS = 7,
-    SIGFPE = 8,
-    SIGKILL = 9,
-    SIGUSR1 = 10,
-    SIGSEGV = 11,
-    SIGUSR2 = 12,
-    SIGPIPE = 13,
-    SIGALRM = 14,
-    SIGTERM = 15,
-    SIGSTKFLT = 16,
-    SIGCHLD = 17,
-    SIGCONT = 18,
-    SIGSTOP = 19,
-    SIGTSTP = 20,
-    SIGTTIN = 21,
-    SIGTTOU = 22,
-    SIGURG = 23,
-    SIGXCPU = 24,
-    SIGXFSZ = 25,
-    SIGVTALRM = 26,
-    SIGPROF = 27,
-    SIGWINCH = 28,
-    SIGIO = 29,
-    SIGPWR = 30,
-    SIGSYS = 31,
+/// A process in the session of a project
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: libc::pid_t,
+    pub parent: Option<libc::pid_t>,
+    pub command: String,
+    pub state: String,
+    pub cpu_percent: f32,
+    pub rss_bytes: u64,
+}
+
+/// The processes in each of the sessions, sorted by pid. The processes are sampled twice to be
+/// able to tell their cpu usage, so this takes a moment
+pub fn session_processes(sids: &[libc::pid_t]) -> HashMap<libc::pid_t, Vec<ProcessInfo>> {
+    let mut sessions: HashMap<_, Vec<_>> = HashMap::new();
+    if sids.is_empty() {
+        return sessions;
+    }
+
+    let refresh = ProcessRefreshKind::nothing()
+        .with_cpu()
+        .with_memory()
+        .with_cmd(UpdateKind::OnlyIfNotSet);
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh);
+    std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+    sys.refresh_processes_specifics(ProcessesToUpdate::All, true, refresh);
+
+    for (pid, p) in sys.processes() {
+        let Some(sid) = p.session_id().map(|it| it.as_u32() as libc::pid_t) else {
+            continue;
+        };
+        // Threads are listed as processes too, but their usage is part of the process
+        if p.status() == ProcessStatus::Zombie || p.thread_kind().is_some() || !sids.contains(&sid)
+        {
+            continue;
+        }
+
+        let command = match p.cmd() {
+            [] => p.name().to_string_lossy().to_string(),
+            cmd => cmd
+                .iter()
+                .map(|it| it.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" "),
+        };
+
+        sessions.entry(sid).or_default().push(ProcessInfo {
+            pid: pid.as_u32() as libc::pid_t,
+            parent: p.parent().map(|it| it.as_u32() as libc::pid_t),
+            command,
+            state: p.status().to_string().to_lowercase(),
+            cpu_percent: p.cpu_usage(),
+            rss_bytes: p.memory(),
+        });
+    }
+
+    for processes in sessions.values_mut() {
+        processes.sort_by_key(|it| it.pid);
+    }
+
+    sessions
+}
+
+/// How a child process terminated, decoded from the raw `waitpid` status
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExitStatus {
+    Code(i32),
+    Signal(i32),
+}
+
+impl ExitStatus {
+    fn from_raw(status: i32) -> Self {
+        if libc::WIFSIGNALED(status) {
+            ExitStatus::Signal(libc::WTERMSIG(status))
+        } else {
+            ExitStatus::Code(libc::WEXITSTATUS(status))
+        }
+    }
+
+    pub fn success(&self) -> bool {
+        matches!(self, ExitStatus::Code(0))
+    }
+}
+
+impl From<std::process::ExitStatus> for ExitStatus {
+    fn from(status: std::process::ExitStatus) -> Self {
+        use std::os::unix::process::ExitStatusExt;
+
+        Self::from_raw(status.into_raw())
+    }
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitStatus::Code(code) => write!(f, "exited with code {}", code),
+            ExitStatus::Signal(signal) => write!(f, "killed by signal {}", signal),
+        }
+    }
+}
+
+// The numbers of the signals differ between platforms, so they are taken from `libc`
+macro_rules! signals {
+    ($($(#[$meta:meta])* $name:ident,)*) => {
+        #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+        #[non_exhaustive]
+        #[allow(clippy::upper_case_acronyms)]
+        pub enum Signal {
+            $($(#[$meta])* $name,)*
+        }
+
+        impl Signal {
+            pub const ALL: &'static [Signal] = &[$($(#[$meta])* Signal::$name,)*];
+
+            pub fn as_raw(&self) -> i32 {
+                match self {
+                    $($(#[$meta])* Signal::$name => libc::$name,)*
+                }
+            }
+
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $($(#[$meta])* Signal::$name => stringify!($name),)*
+                }
+            }
+        }
+    };
+}
+
+signals! {
+    SIGHUP,
+    SIGINT,
+    SIGQUIT,
+    SIGILL,
+    SIGTRAP,
+    SIGABRT,
+    SIGBUS,
+    SIGFPE,
+    SIGKILL,
+    SIGUSR1,
+    SIGSEGV,
+    SIGUSR2,
+    SIGPIPE,
+    SIGALRM,
+    SIGTERM,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    SIGSTKFLT,
+    SIGCHLD,
+    SIGCONT,
+    SIGSTOP,
+    SIGTSTP,
+    SIGTTIN,
+    SIGTTOU,
+    SIGURG,
+    SIGXCPU,
+    SIGXFSZ,
+    SIGVTALRM,
+    SIGPROF,
+    SIGWINCH,
+    SIGIO,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    SIGPWR,
+    SIGSYS,
+}
+
+impl Signal {
+    pub fn from_raw(signal: i32) -> Option<Signal> {
+        Signal::ALL.iter().find(|it| it.as_raw() == signal).copied()
+    }
+}
+
+impl std::fmt::Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Accepts the name with or without `SIG` in any case, e.g. `SIGTERM`, `term`, or the number
+impl std::str::FromStr for Signal {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Ok(number) = s.parse::<i32>() {
+            return Signal::from_raw(number).ok_or_else(|| anyhow!("Unknown signal {}", number));
+        }
+
+        let name = s.to_ascii_uppercase();
+        let name = name.strip_prefix("SIG").unwrap_or(&name);
+        Signal::ALL
+            .iter()
+            .find(|it| it.name()[3..] == *name)
+            .copied()
+            .ok_or_else(|| anyhow!("Unknown signal {}", s))
+    }
+}
+
+impl Serialize for Signal {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+impl<'de> Deserialize<'de> for Signal {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(i32),
+            Name(String),
+        }
+
+        let signal = match Raw::deserialize(deserializer)? {
+            Raw::Number(number) => number.to_string(),
+            Raw::Name(name) => name,
+        };
+        signal.parse().map_err(serde::de::Error::custom)
+    }
 }
@@ -0,0 +1,455 @@
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufReader, Read, Seek, SeekFrom},
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use flate2::{write::GzEncoder, Compression};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{
+    config::{LogConfig, Project, WorkerConfig},
+    libc::{mktime, LocalTime},
+};
+
+const DEFAULT_KEEP: u32 = 1;
+
+// Logs are tailed by reading them backwards, starting with a block of this size. Every block is
+// twice the size of the one before it
+const TAIL_BLOCK: u64 = 64 * 1024;
+
+// A timestamp at the start of a line, e.g. `2024-05-01T12:00:00.123Z` or `[2024-05-01 12:00:00]`,
+// possibly after ansi color codes
+static TIMESTAMP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^(?:\x1b\[[0-9;]*m)*\[?(\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}:\d{2}(?:\.\d+)?)(Z|[+-]\d{2}:?\d{2})?",
+    )
+    .unwrap()
+});
+
+// A line captured through a pipe, e.g. `2024-05-01T12:00:00.123Z stderr Something went wrong`
+static CAPTURED: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?Z) (stdout|stderr) (.*)$").unwrap()
+});
+
+/// Parse the timestamp at the start of a log line, taking the offset into account. Timestamps
+/// without an offset are in local time
+fn line_time(line: &str) -> Option<SystemTime> {
+    let captures = TIMESTAMP.captures(line)?;
+    let time = humantime::parse_rfc3339_weak(&captures[1]).ok()?;
+
+    let offset = match captures.get(2).map(|it| it.as_str()) {
+        None => return local(time),
+        Some("Z") => return Some(time),
+        Some(offset) => offset,
+    };
+
+    let digits = offset[1..].replace(':', "");
+    let hours: u64 = digits[..2].parse().ok()?;
+    let minutes: u64 = digits[2..].parse().ok()?;
+    let duration = Duration::from_secs(hours * 3600 + minutes * 60);
+
+    if offset.starts_with('-') {
+        time.checked_add(duration)
+    } else {
+        time.checked_sub(duration)
+    }
+}
+
+// The time of a timestamp in local time, given the time it would be in UTC
+fn local(utc: SystemTime) -> Option<SystemTime> {
+    let since = utc.duration_since(UNIX_EPOCH).ok()?;
+    // The minutes carry over into the date, and the time zone decides the offset at that date
+    let time = mktime(&LocalTime {
+        year: 1970,
+        month: 1,
+        day: 1,
+        hour: 0,
+        minute: i32::try_from(since.as_secs() / 60).ok()?,
+        weekday: 0,
+    })?;
+
+    let seconds = Duration::new(since.as_secs() % 60, since.subsec_nanos());
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(time).ok()?) + seconds)
+}
+
+/// Parse a time given on the command line. Either a duration relative to now, e.g. `10m`, or a
+/// timestamp in local time, e.g. `2024-05-01 12:00:00`, or in UTC with a `Z` at the end
+pub fn parse_time(s: &str) -> Result<SystemTime, anyhow::Error> {
+    if let Ok(duration) = humantime::parse_duration(s) {
+        return SystemTime::now()
+            .checked_sub(duration)
+            .ok_or_else(|| anyhow!("Invalid time: {}", s));
+    }
+
+    let (naive, utc) = match s.strip_suffix('Z') {
+        Some(naive) => (naive, true),
+        None => (s, false),
+    };
+    humantime::parse_rfc3339_weak(naive)
+        .or_else(|_| humantime::parse_rfc3339_weak(&format!("{} 00:00:00", naive)))
+        .ok()
+        .and_then(|time| if utc { Some(time) } else { local(time) })
+        .ok_or_else(|| {
+            anyhow!(
+                "Invalid time: {}. Expected e.g. `10m` or `2024-05-01 12:00:00`",
+                s
+            )
+        })
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl std::fmt::Display for Stream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Stream::Stdout => write!(f, "stdout"),
+            Stream::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LogFilter {
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+    pub grep: Option<Regex>,
+    pub stderr_only: bool,
+}
+
+impl LogFilter {
+    // Lines without a known time or stream never match a filter on them
+    fn matches(&self, line: &LogLine) -> bool {
+        let LogLine { text, time, stream } = line;
+
+        if self.stderr_only && *stream != Some(Stream::Stderr) {
+            return false;
+        }
+
+        if self
+            .since
+            .is_some_and(|since| time.is_none_or(|time| time < since))
+        {
+            return false;
+        }
+
+        if self
+            .until
+            .is_some_and(|until| time.is_none_or(|time| time > until))
+        {
+            return false;
+        }
+
+        self.grep.as_ref().is_none_or(|grep| grep.is_match(text))
+    }
+}
+
+pub struct LogLine {
+    pub text: String,
+    pub time: Option<SystemTime>,
+    /// Only known when the output was captured
+    pub stream: Option<Stream>,
+}
+
+/// Interleave the lines of several logs by time, keeping the order within each log. When any of
+/// the lines has no known time, the logs are kept in the order they were read instead, one after
+/// the other. Returns the index of the log each line came from
+pub fn merge(logs: Vec<Vec<LogLine>>) -> Vec<(usize, LogLine)> {
+    if logs.iter().flatten().any(|it| it.time.is_none()) {
+        return logs
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, lines)| lines.into_iter().map(move |it| (i, it)))
+            .collect();
+    }
+
+    let mut logs: Vec<VecDeque<LogLine>> = logs.into_iter().map(VecDeque::from).collect();
+    let mut merged = Vec::new();
+
+    while let Some(i) = (0..logs.len())
+        .filter(|i| !logs[*i].is_empty())
+        .min_by_key(|i| logs[*i][0].time)
+    {
+        merged.push((i, logs[i].pop_front().unwrap()));
+    }
+
+    merged
+}
+
+/// Reads lines from a log file. Keeps track of the position in the file to be able to follow it,
+/// and starts from the beginning when the file is truncated or replaced, e.g. on restart
+pub struct LogReader {
+    path: PathBuf,
+    file: Option<File>,
+    inode: u64,
+    position: u64,
+    partial: Vec<u8>,
+    // Lines without a timestamp, e.g. stack traces, belong to the last line with a timestamp
+    last_time: Option<SystemTime>,
+}
+
+impl LogReader {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            file: None,
+            inode: 0,
+            position: 0,
+            partial: Vec::new(),
+            last_time: None,
+        }
+    }
+
+    /// The last `n` lines of the file matching the filter. The file is read backwards until they
+    /// are found, and following reads continue from the end
+    pub fn tail(&mut self, n: usize, filter: &LogFilter) -> Result<Vec<LogLine>, anyhow::Error> {
+        let Ok(mut file) = File::open(&self.path) else {
+            return Ok(Vec::new());
+        };
+        let metadata = file.metadata()?;
+        let end = metadata.len();
+
+        // Lines without a timestamp get their time from a line before them, which may not
+        // have been read yet
+        let timed = filter.since.is_some() || filter.until.is_some();
+        let mut start = end;
+        let mut block = TAIL_BLOCK;
+        let mut buf = Vec::new();
+        let lines = loop {
+            let size = start.min(block);
+            start -= size;
+            block *= 2;
+            let mut chunk = vec![0; size as usize];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut chunk)?;
+            chunk.append(&mut buf);
+            buf = chunk;
+
+            // The first line may have started before the start of what was read
+            let first = match start {
+                0 => Some(0),
+                _ => buf.iter().position(|it| *it == b'\n').map(|i| i + 1),
+            };
+            let last = buf.iter().rposition(|it| *it == b'\n');
+            let (Some(first), Some(last)) = (first, last) else {
+                if start == 0 {
+                    break Vec::new();
+                }
+                continue;
+            };
+
+            self.last_time = None;
+            let lines = match first <= last {
+                true => self.parse(&buf[first..last]),
+                false => Vec::new(),
+            };
+            let lines: Vec<_> = lines
+                .into_iter()
+                .skip_while(|it| timed && start > 0 && it.time.is_none())
+                .filter(|it| filter.matches(it))
+                .collect();
+            if lines.len() >= n || start == 0 {
+                break lines;
+            }
+        };
+
+        self.file = Some(file);
+        self.inode = metadata.ino();
+        self.position = end;
+        self.partial = match buf.iter().rposition(|it| *it == b'\n') {
+            Some(last) => buf.split_off(last + 1),
+            None => buf,
+        };
+
+        Ok(lines.into_iter().rev().take(n).rev().collect())
+    }
+
+    /// Lines written since the last read matching the filter
+    pub fn poll(&mut self, filter: &LogFilter) -> Result<Vec<LogLine>, anyhow::Error> {
+        Ok(self
+            .read()?
+            .into_iter()
+            .filter(|it| filter.matches(it))
+            .collect())
+    }
+
+    // Read the complete lines written since the last read, together with their time
+    fn read(&mut self) -> Result<Vec<LogLine>, anyhow::Error> {
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            return Ok(Vec::new());
+        };
+
+        if self.file.is_none() || metadata.ino() != self.inode || metadata.len() < self.position {
+            self.file = Some(File::open(&self.path)?);
+            self.inode = metadata.ino();
+            self.position = 0;
+            self.partial.clear();
+        }
+
+        let Some(ref mut file) = self.file else {
+            return Ok(Vec::new());
+        };
+
+        file.seek(SeekFrom::Start(self.position))?;
+        let mut buf = Vec::new();
+        self.position += file.read_to_end(&mut buf)? as u64;
+
+        self.partial.extend(buf);
+        let Some(end) = self.partial.iter().rposition(|it| *it == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+
+        Ok(self.parse(&complete[..end]))
+    }
+
+    // Split the lines, and find their time. The last line doesn't end with a newline
+    fn parse(&mut self, complete: &[u8]) -> Vec<LogLine> {
+        let mut lines = Vec::new();
+        for line in complete.split(|it| *it == b'\n') {
+            let line = String::from_utf8_lossy(line);
+            let mut stream = None;
+            let text = match CAPTURED.captures(&line) {
+                Some(captured) => {
+                    self.last_time = humantime::parse_rfc3339(&captured[1]).ok();
+                    stream = Some(match &captured[2] {
+                        "stderr" => Stream::Stderr,
+                        _ => Stream::Stdout,
+                    });
+                    captured[3].to_string()
+                }
+                None => {
+                    if let Some(time) = line_time(&line) {
+                        self.last_time = Some(time);
+                    }
+                    line.to_string()
+                }
+            };
+
+            lines.push(LogLine {
+                text,
+                time: self.last_time,
+                stream,
+            });
+        }
+
+        lines
+    }
+}
+
+// Previous generations are named after the log file, e.g. `backend.1` and `backend.2.gz`
+fn generation_path(log: &Path, n: u32, compressed: bool) -> PathBuf {
+    let mut path = log.as_os_str().to_owned();
+    path.push(format!(".{}", n));
+    if compressed {
+        path.push(".gz");
+    }
+    PathBuf::from(path)
+}
+
+/// The log of the previous run of the project, if it is kept
+pub fn previous(config: &WorkerConfig, project: &Project) -> Option<PathBuf> {
+    let path = generation_path(&config.log_file(project), 1, false);
+    path.exists().then_some(path)
+}
+
+/// Rotate or remove the log before the project is started. The project gets a new file either
+/// way, which lets anyone following the log notice that it was replaced
+pub fn rotate_on_start(config: &WorkerConfig, project: &Project) -> Result<(), anyhow::Error> {
+    let log = project.log.clone().unwrap_or_default();
+    if log.rotate_on_start.unwrap_or(true) {
+        return rotate(config, project, false);
+    }
+
+    match std::fs::remove_file(config.log_file(project)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+pub fn rotate_if_too_large(config: &WorkerConfig, project: &Project) -> Result<(), anyhow::Error> {
+    let log = project.log.clone().unwrap_or_default();
+    let Some(max_size) = log.max_size else {
+        return Ok(());
+    };
+
+    match std::fs::metadata(config.log_file(project)) {
+        Ok(metadata) if metadata.len() > max_size => rotate(config, project, true),
+        _ => Ok(()),
+    }
+}
+
+/// Move the current log to generation 1, and every previous generation one step back. The
+/// oldest generation is removed. The current log is copied and truncated instead of moved with
+/// `copy`, for when the project is still writing to it
+fn rotate(config: &WorkerConfig, project: &Project, copy: bool) -> Result<(), anyhow::Error> {
+    let path = config.log_file(project);
+    if std::fs::metadata(&path).map_or(true, |it| it.len() == 0) {
+        return Ok(());
+    }
+
+    let LogConfig { keep, compress, .. } = project.log.clone().unwrap_or_default();
+    let keep = keep.unwrap_or(DEFAULT_KEEP);
+    let compress = compress.unwrap_or(false);
+
+    if keep == 0 && copy {
+        return truncate(&path);
+    } else if keep == 0 {
+        return Ok(std::fs::remove_file(&path)?);
+    }
+
+    for compressed in [false, true] {
+        let _ = std::fs::remove_file(generation_path(&path, keep, compressed));
+    }
+
+    for n in (1..keep).rev() {
+        for compressed in [false, true] {
+            let from = generation_path(&path, n, compressed);
+            if !from.exists() {
+                continue;
+            }
+
+            if compress && !compressed {
+                gzip(&from, &generation_path(&path, n + 1, true))?;
+                std::fs::remove_file(&from)?;
+            } else {
+                std::fs::rename(&from, generation_path(&path, n + 1, compressed))?;
+            }
+        }
+    }
+
+    if copy {
+        std::fs::copy(&path, generation_path(&path, 1, false))?;
+        truncate(&path)
+    } else {
+        Ok(std::fs::rename(&path, generation_path(&path, 1, false))?)
+    }
+}
+
+fn truncate(path: &Path) -> Result<(), anyhow::Error> {
+    if path.exists() {
+        OpenOptions::new().write(true).open(path)?.set_len(0)?;
+    }
+
+    Ok(())
+}
+
+fn gzip(from: &Path, to: &Path) -> Result<(), anyhow::Error> {
+    let mut input = BufReader::new(File::open(from)?);
+    let mut encoder = GzEncoder::new(File::create(to)?, Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?;
+
+    Ok(())
+}
@@ -1,132 +1,571 @@
 use std::{
-    fs::OpenOptions,
-    os::{
-        fd::{FromRawFd, IntoRawFd},
-        unix::process::CommandExt,
-    },
-    process::Stdio,
+    io::{IsTerminal, StdoutLock, Write},
     time::{Duration, Instant},
 };
 
-use anyhow::{anyhow, Context};
-use clap::{command, Parser};
-use config::{Project, WorkerConfig};
+use anyhow::anyhow;
+use clap::Parser;
+use config::{Project, ProjectKind, RunningProject, WorkerConfig};
+use daemon::{Client, Request};
+use format::{
+    format_time, serialize_line, Format, LogOutput, ProjectOutput, ProjectStatus, State,
+    StatusOutput, StoppedOutput,
+};
 use itertools::Itertools;
-use libc::{fork, setsid, waitpid, Fork};
+use libc::{close_cloexec_fds, fork, setsid, waitpid, Fork, Signal};
+use logs::{LogFilter, LogLine, LogReader};
+use regex::Regex;
 
+pub mod attach;
 pub mod config;
+pub mod daemon;
+pub mod env;
+pub mod format;
+pub mod health;
 pub mod libc;
+pub mod logs;
+pub mod run;
+pub mod schedule;
+pub mod shim;
+pub mod ui;
+pub mod validate;
+pub mod watch;
+
+// Colors of the project prefixes, in the same order as docker compose
+const COLORS: [u8; 6] = [36, 33, 32, 35, 34, 31];
+
+fn logs(
+    config: &WorkerConfig,
+    projects: Vec<Project>,
+    args: LogsArgs,
+) -> Result<(), anyhow::Error> {
+    let mut files = Vec::new();
+    for project in projects.iter() {
+        let file = if args.previous {
+            logs::previous(config, project)
+                .ok_or_else(|| anyhow!("No previous log for {}", project))
+        } else if config.is_running(project)? {
+            Ok(config.log_file(project))
+        } else {
+            Err(anyhow!("{} is not running", project))
+        };
+
+        // Only fail when there is nothing to print
+        match file {
+            Ok(file) => files.push((project, file)),
+            Err(e) if projects.len() == 1 => return Err(e),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
 
-fn logs(config: &WorkerConfig, args: LogsArgs) -> Result<(), anyhow::Error> {
-    if !config.is_running(&args.project)? {
-        return Err(anyhow!("{} is not running", args.project));
+    if files.is_empty() {
+        return Err(anyhow!("None of the projects have a log to print"));
     }
 
-    let mut cmd = std::process::Command::new("tail");
+    let filter = LogFilter {
+        since: args.since.as_deref().map(logs::parse_time).transpose()?,
+        until: args.until.as_deref().map(logs::parse_time).transpose()?,
+        grep: args.grep.as_deref().map(Regex::new).transpose()?,
+        stderr_only: args.stderr_only,
+    };
+
+    // Lines are prefixed with the project they came from when more than one was asked for
+    let names: Vec<_> = files
+        .iter()
+        .map(|(project, _)| project.display.clone().unwrap_or(project.name.clone()))
+        .collect();
+    let width = names.iter().map(|it| it.chars().count()).max().unwrap_or(0);
+    let color = std::io::stdout().is_terminal();
+    let prefixes: Vec<_> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| match (projects.len(), color) {
+            (1, _) => String::new(),
+            (_, true) => format!(
+                "\x1b[{}m{:width$} |\x1b[0m ",
+                COLORS[i % COLORS.len()],
+                name
+            ),
+            (_, false) => format!("{:width$} | ", name),
+        })
+        .collect();
+
+    let names: Vec<_> = files.iter().map(|(it, _)| it.name.clone()).collect();
+    let mut readers: Vec<_> = files
+        .into_iter()
+        .map(|(_, file)| LogReader::new(file))
+        .collect();
+
+    // Stop quietly if the output is closed, e.g. when piping to `head`
+    match print_logs(&mut readers, &names, &prefixes, &filter, &args) {
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        res => res,
+    }
+}
 
-    if args.follow {
-        cmd.arg("-f");
+fn print_logs(
+    readers: &mut [LogReader],
+    names: &[String],
+    prefixes: &[String],
+    filter: &LogFilter,
+    args: &LogsArgs,
+) -> Result<(), anyhow::Error> {
+    let mut stdout = std::io::stdout().lock();
+
+    // The lines are streamed, so the columns of the table can't depend on them
+    let width = names
+        .iter()
+        .map(|it| it.chars().count())
+        .chain([7])
+        .max()
+        .unwrap();
+    if args.format == Format::Table {
+        writeln!(stdout, "{:24}  {:width$}  STREAM  LINE", "TIME", "PROJECT")?;
     }
 
-    let mut child = cmd
-        .args(["-n", &args.number.to_string()])
-        .arg(config.log_file(&args.project))
-        .spawn()?;
+    let print = |stdout: &mut StdoutLock, i: usize, line: LogLine| -> Result<(), anyhow::Error> {
+        match (args.format, line.time) {
+            // Only captured lines have a timestamp separate from the line itself
+            (Format::Text, Some(time)) if args.timestamps && line.stream.is_some() => {
+                writeln!(stdout, "{}{} {}", prefixes[i], format_time(time), line.text)?
+            }
+            (Format::Text, _) => writeln!(stdout, "{}{}", prefixes[i], line.text)?,
+            (Format::Table, time) => writeln!(
+                stdout,
+                "{:24}  {:width$}  {:6}  {}",
+                time.map(format_time).unwrap_or("-".to_string()),
+                names[i],
+                line.stream.map_or("-".to_string(), |it| it.to_string()),
+                line.text
+            )?,
+            (format, _) => {
+                let output = LogOutput::new(&names[i], line);
+                writeln!(
+                    stdout,
+                    "{}",
+                    serialize_line(format, &output)?.unwrap_or_default()
+                )?
+            }
+        };
+
+        Ok(())
+    };
 
-    child.wait()?;
+    let lines = readers
+        .iter_mut()
+        .map(|it| it.tail(args.number, filter))
+        .collect::<Result<_, _>>()?;
+    for (i, line) in logs::merge(lines) {
+        print(&mut stdout, i, line)?;
+    }
 
-    Ok(())
+    if !args.follow {
+        return Ok(());
+    }
+
+    loop {
+        stdout.flush()?;
+        std::thread::sleep(Duration::from_millis(100));
+
+        let lines = readers
+            .iter_mut()
+            .map(|it| it.poll(filter))
+            .collect::<Result<_, _>>()?;
+        for (i, line) in logs::merge(lines) {
+            print(&mut stdout, i, line)?;
+        }
+    }
+}
+
+// The status of the running projects, or of every project in the config with `all`
+fn statuses(config: &WorkerConfig, running: Vec<RunningProject>, all: bool) -> Vec<ProjectStatus> {
+    let sids: Vec<_> = running.iter().map(|it| it.sid()).collect();
+    let mut sessions = libc::session_processes(&sids);
+    let mut statuses: Vec<_> = running
+        .iter()
+        .map(|it| {
+            let uptime = config.uptime(it).map(|it| it.as_secs());
+            // Projects run in the foreground share the session with the terminal
+            let processes = match it.foreground {
+                true => sessions
+                    .get(&it.sid())
+                    .map(|processes| format::descendants(processes, it.pid))
+                    .unwrap_or_default(),
+                false => sessions.remove(&it.pid).unwrap_or_default(),
+            };
+            let status = StatusOutput::new(it, uptime, health::health(config, it), processes);
+            ProjectStatus::Running(status)
+        })
+        .collect();
+
+    if !all {
+        return statuses;
+    }
+
+    // Every project in the order of the config, with the last run of the ones not running.
+    // Projects removed from the config while running come last
+    let mut all: Vec<_> = config
+        .projects
+        .iter()
+        .map(|project| {
+            let index = statuses.iter().position(
+                |it| matches!(it, ProjectStatus::Running(it) if it.project.name == project.name),
+            );
+            match index {
+                Some(index) => statuses.remove(index),
+                None => {
+                    ProjectStatus::Stopped(StoppedOutput::new(project, config.last_run(project)))
+                }
+            }
+        })
+        .collect();
+    all.append(&mut statuses);
+    all
 }
 
 fn status(config: &WorkerConfig, args: StatusArgs) -> Result<(), anyhow::Error> {
-    for project in config.running()? {
-        if args.quiet {
-            println!("{}", project.name);
+    let running = config.running()?;
+
+    if args.quiet {
+        for project in running {
+            println!("{}", project.project.name);
+        }
+        return Ok(());
+    }
+
+    let statuses = statuses(config, running, args.all);
+
+    if let Some(output) = format::serialize(args.format, &statuses)? {
+        println!("{}", output);
+        return Ok(());
+    }
+
+    let ago = |time: Option<u64>| {
+        time.map(|it| {
+            let secs = daemon::now().saturating_sub(it);
+            format!(
+                "{} ago",
+                humantime::format_duration(Duration::from_secs(secs))
+            )
+        })
+    };
+    let uptime = |status: &StatusOutput| {
+        status.uptime_secs.map_or("-".to_string(), |it| {
+            humantime::format_duration(Duration::from_secs(it)).to_string()
+        })
+    };
+
+    if args.format == Format::Table {
+        let rows = statuses
+            .iter()
+            .map(|it| match it {
+                ProjectStatus::Running(it) => vec![
+                    it.project.name.clone(),
+                    it.state.to_string(),
+                    it.sid.to_string(),
+                    uptime(it),
+                    format!("{:.1}%", it.cpu_percent),
+                    format::format_bytes(it.rss_bytes),
+                    it.children.to_string(),
+                    it.health.map_or("-".to_string(), |it| it.to_string()),
+                    it.restarts.to_string(),
+                ],
+                ProjectStatus::Stopped(it) => {
+                    let mut row = vec![it.project.name.clone(), it.state.to_string()];
+                    row.extend(["-"; 7].map(String::from));
+                    row
+                }
+            })
+            .collect();
+        let header = [
+            "NAME", "STATE", "SID", "UPTIME", "CPU", "RSS", "CHILDREN", "HEALTH", "RESTARTS",
+        ];
+        let table = format::table(&header, rows);
+
+        // The processes of each project are listed under its row
+        let mut lines = table.lines();
+        println!("{}", lines.next().unwrap_or_default());
+        for (line, status) in lines.zip(statuses.iter()) {
+            println!("{}", line);
+            if let (true, ProjectStatus::Running(status)) = (args.tree, status) {
+                for process in format::tree(&status.processes) {
+                    println!("  {}", process);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    for status in statuses.iter() {
+        let status = match status {
+            ProjectStatus::Running(status) => status,
+            ProjectStatus::Stopped(stopped) => {
+                let project = &stopped.project;
+                let ago = ago(stopped.stopped_at).unwrap_or("unknown".to_string());
+                // Tasks run to completion, so how long they took is of interest
+                let took = match (project.kind, stopped.duration_secs) {
+                    (ProjectKind::Task, Some(secs)) => {
+                        format!(
+                            " after {}",
+                            humantime::format_duration(Duration::from_secs(secs))
+                        )
+                    }
+                    _ => String::new(),
+                };
+                let line = match (stopped.state, stopped.exit, &stopped.stopped_by) {
+                    _ if stopped.started_at.is_none() => format!("{} was never started", project),
+                    (State::Stopped, _, Some(user)) => {
+                        format!("{} is stopped (by {}, {})", project, user, ago)
+                    }
+                    (State::Crashed, Some(exit), _) => {
+                        format!("{} crashed, {}{} ({})", project, exit, took, ago)
+                    }
+                    (State::Exited, Some(exit), _) => {
+                        format!("{} {}{} ({})", project, exit, took, ago)
+                    }
+                    _ => format!("{} is stopped ({})", project, ago),
+                };
+                println!("{}", line);
+                if let Some(next_run) = stopped.next_run {
+                    let secs = next_run.saturating_sub(daemon::now());
+                    println!(
+                        "  next run in {}",
+                        humantime::format_duration(Duration::from_secs(secs))
+                    );
+                }
+                continue;
+            }
+        };
+
+        let mut details = Vec::new();
+        if let Some(health) = status.health {
+            details.push(health.to_string());
+        }
+        if let Some(last_exit) = status.last_exit {
+            details.push(format!("restarts: {}", status.restarts));
+            details.push(format!("last {}", last_exit));
+        }
+
+        if details.is_empty() {
+            println!("{} is running", status.project);
         } else {
-            println!("{} is running", project);
+            println!("{} is running ({})", status.project, details.join(", "));
+        }
+
+        println!(
+            "  sid {}, uptime {}, cpu {:.1}%, rss {}, children {}",
+            status.sid,
+            uptime(status),
+            status.cpu_percent,
+            format::format_bytes(status.rss_bytes),
+            status.children
+        );
+        if args.tree {
+            for process in format::tree(&status.processes) {
+                println!("  {}", process);
+            }
         }
     }
 
     Ok(())
 }
 
-fn stop(config: &WorkerConfig, projects: Vec<Project>) -> Result<(), anyhow::Error> {
+fn stop(config: &WorkerConfig, projects: Vec<Project>, force: bool) -> Result<(), anyhow::Error> {
     let (running, not_running) = config.partition_projects(projects)?;
 
-    for project in running.iter() {
-        project.stop()?;
+    for project in not_running.iter() {
+        eprintln!("Cannot stop project not running: {}", project);
     }
 
-    for project in not_running {
-        eprintln!("Cannot stop project not running: {}", project);
+    // Tell the daemon to not restart the projects, including the ones it is waiting to restart
+    if let Some(client) = Client::connect(config) {
+        let names = running
+            .iter()
+            .map(|it| it.project.name.clone())
+            .chain(not_running.into_iter().map(|it| it.name))
+            .collect();
+        // The projects can still be stopped without the daemon
+        if let Err(e) = client.send(Request::Stop(names)) {
+            eprintln!("Couldn't reach the daemon: {}", e);
+        }
     }
 
-    let timeout = Duration::new(5, 0);
-    let start = Instant::now();
+    for project in running.iter() {
+        config.record_stop(&project.project, project.pid)?;
+    }
 
-    while Instant::now().duration_since(start) < timeout {
-        let (still_running, _) = config.partition_projects(running.clone())?;
-        if still_running.is_empty() {
-            return Ok(());
-        }
+    // Stop projects before the projects they depend on
+    let mut survivors = Vec::new();
+    for batch in config.stop_order(running) {
+        survivors.extend(stop_batch(config, batch, force)?);
     }
 
-    let (still_running, _) = config.partition_projects(running)?;
-    for p in still_running {
+    for p in survivors.iter() {
         eprintln!("Was not able to stop {}", p);
     }
 
+    if !survivors.is_empty() {
+        return Err(anyhow!("{} project(s) are still running", survivors.len()));
+    }
+
     Ok(())
 }
 
-fn start(config: &WorkerConfig, projects: Vec<Project>) -> Result<(), anyhow::Error> {
+fn signal(
+    config: &WorkerConfig,
+    projects: Vec<Project>,
+    signal: Signal,
+) -> Result<(), anyhow::Error> {
     let (running, not_running) = config.partition_projects(projects)?;
 
+    for project in not_running.iter() {
+        eprintln!("Cannot signal project not running: {}", project);
+    }
+
+    for project in running.iter() {
+        project.signal(&signal)?;
+    }
+
+    Ok(())
+}
+
+// Send the stop signals of each project as they are due, until every project has stopped or
+// is past its deadline. Returns the projects still running
+fn stop_batch(
+    config: &WorkerConfig,
+    batch: Vec<RunningProject>,
+    force: bool,
+) -> Result<Vec<RunningProject>, anyhow::Error> {
+    let mut pending: Vec<_> = batch
+        .iter()
+        .map(|it| (it.project.name.clone(), it.stop_sequence(force)))
+        .collect();
+    let start = Instant::now();
+
+    loop {
+        let elapsed = Instant::now().duration_since(start);
+        let (still_running, _) = config.partition_projects(batch.clone())?;
+
+        for project in still_running.iter() {
+            let Some((_, sequence)) = pending
+                .iter_mut()
+                .find(|(name, _)| *name == project.project.name)
+            else {
+                continue;
+            };
+
+            while sequence.first().is_some_and(|(after, _)| *after <= elapsed) {
+                let (_, signal) = sequence.remove(0);
+                project.signal(&signal)?;
+            }
+        }
+
+        if still_running
+            .iter()
+            .all(|it| elapsed >= it.stop_deadline(force))
+        {
+            return Ok(still_running);
+        }
+
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn start(config: &WorkerConfig, projects: Vec<Project>, wait: bool) -> Result<(), anyhow::Error> {
+    let requested: Vec<_> = projects.iter().map(|it| it.name.clone()).collect();
+    let (running, not_running) = config.partition_projects(config.with_dependencies(projects))?;
+
+    // Dependencies that were not asked for are expected to already be running
     for project in running {
-        eprintln!("{} is already running", project);
+        if requested.contains(&project.project.name) {
+            eprintln!("{} is already running", project);
+        }
     }
 
+    let mut started: Vec<Project> = Vec::new();
     for project in not_running {
-        match fork().expect("Couldn't fork") {
-            Fork::Parent(p) => {
-                waitpid(p).unwrap();
+        // Dependencies with a healthcheck needs to be ready before the project is started
+        for dependency in started.iter().filter(|it| {
+            it.healthcheck.is_some() && project.depends_on.iter().flatten().any(|d| *d == it.name)
+        }) {
+            health::wait_until_healthy(config, dependency)?;
+        }
+        // Tasks need to finish successfully before the projects depending on them start
+        for dependency in started.iter().filter(|it| {
+            it.kind == ProjectKind::Task
+                && project.depends_on.iter().flatten().any(|d| *d == it.name)
+        }) {
+            health::wait_until_finished(config, dependency)
+                .map_err(|e| anyhow!("Dependency of {} failed: {}", project, e))?;
+        }
+
+        // Another worker may have started the project while this one was waiting for the lock
+        let _lock = config.lock(&project)?;
+        if config.is_running(&project)? {
+            if requested.contains(&project.name) {
+                eprintln!("{} is already running", project);
             }
-            Fork::Child => {
-                let sid = setsid().expect("Couldn't setsid");
-                config.store_state(sid, &project)?;
-
-                match fork().expect("Couldn't fork inner") {
-                    Fork::Parent(_) => std::process::exit(0),
-                    Fork::Child => {
-                        // Create a raw filedescriptor to use to merge stdout and stderr
-                        let fd = OpenOptions::new()
-                            .write(true)
-                            .truncate(true)
-                            .create(true)
-                            .open(config.log_file(&project))?
-                            .into_raw_fd();
-
-                        let parts = shlex::split(&project.command)
-                            .context(format!("Couldn't parse command: {}", project.command))?;
-
-                        std::process::Command::new(&parts[0])
-                            .args(&parts[1..])
-                            .envs(project.envs.unwrap_or_default())
-                            .current_dir(project.cwd)
-                            .stdout(unsafe { Stdio::from_raw_fd(fd) })
-                            .stderr(unsafe { Stdio::from_raw_fd(fd) })
-                            .stdin(Stdio::null())
-                            .exec();
-                    }
-                };
+            continue;
+        }
+
+        spawn(config, project.clone())?;
+        started.push(project);
+    }
+
+    if wait {
+        for project in started.iter() {
+            match project.kind {
+                ProjectKind::Service => health::wait_until_healthy(config, project)?,
+                ProjectKind::Task => health::wait_until_finished(config, project)?,
             }
-        };
+        }
     }
 
     Ok(())
 }
 
-fn restart(config: &WorkerConfig, projects: Vec<Project>) -> Result<(), anyhow::Error> {
+fn spawn(config: &WorkerConfig, project: Project) -> Result<(), anyhow::Error> {
+    if let Some(client) = Client::connect(config) {
+        match client.send(Request::Start(vec![project.clone()])) {
+            Ok(_) => return Ok(()),
+            Err(e) if daemon::no_answer(&e) => eprintln!(
+                "Couldn't reach the daemon, starting {} without it: {}",
+                project, e
+            ),
+            Err(e) => return Err(e),
+        }
+    }
+
+    match fork().expect("Couldn't fork") {
+        Fork::Parent(p) => {
+            waitpid(p).unwrap();
+        }
+        Fork::Child => {
+            // The lock of the project would otherwise be held until the project exits
+            close_cloexec_fds();
+            let sid = setsid().expect("Couldn't setsid");
+            config.store_state(&RunningProject::new(project.clone(), sid))?;
+            config.record_start(&project, sid)?;
+
+            match fork().expect("Couldn't fork inner") {
+                Fork::Parent(_) => std::process::exit(0),
+                Fork::Child => {
+                    let err = config.exec(&project, true);
+                    return Err(anyhow!("Couldn't start {}: {}", project, err));
+                }
+            };
+        }
+    };
+
+    Ok(())
+}
+
+fn restart(config: &WorkerConfig, projects: Vec<Project>, wait: bool) -> Result<(), anyhow::Error> {
     let (projects, filtered) = config.partition_projects(projects)?;
     let projects: Vec<Project> = projects.into_iter().map(|p| p.into()).collect();
 
@@ -134,13 +573,39 @@ fn restart(config: &WorkerConfig, projects: Vec<Project>) -> Result<(), anyhow::
         eprintln!("Cannot restart project not running: {}", project);
     }
 
-    stop(config, projects.clone())?;
-    start(config, projects)?;
+    stop(config, projects.clone(), false)?;
+    start(config, projects, wait)?;
 
     Ok(())
 }
 
 fn list(config: &WorkerConfig, args: ListArgs) -> Result<(), anyhow::Error> {
+    if args.format != Format::Text && !args.quiet {
+        let projects: Vec<ProjectOutput> = config.projects.iter().map(|it| it.into()).collect();
+        if let Some(output) = format::serialize(args.format, &projects)? {
+            println!("{}", output);
+            return Ok(());
+        }
+
+        let rows = projects
+            .into_iter()
+            .map(|it| {
+                vec![
+                    it.name,
+                    it.display.unwrap_or_default(),
+                    it.groups.join(","),
+                    it.cwd,
+                    it.command,
+                ]
+            })
+            .collect();
+        println!(
+            "{}",
+            format::table(&["NAME", "DISPLAY", "GROUPS", "CWD", "COMMAND"], rows)
+        );
+        return Ok(());
+    }
+
     for p in config.projects.iter() {
         if args.quiet {
             println!("{}", p.name)
@@ -154,7 +619,7 @@ fn list(config: &WorkerConfig, args: ListArgs) -> Result<(), anyhow::Error> {
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 enum ActionArg {
-    Project(Project),
+    Project(Box<Project>),
     Group(Vec<Project>),
 }
 
@@ -163,42 +628,178 @@ struct ActionArgs {
     projects: Vec<ActionArg>,
 }
 
+#[derive(Debug, Parser)]
+struct StopArgs {
+    #[command(flatten)]
+    action: ActionArgs,
+
+    #[arg(short, long, help = "Kill the project(s) with SIGKILL right away")]
+    force: bool,
+}
+
+#[derive(Debug, Parser)]
+struct SignalArgs {
+    project: ActionArg,
+
+    #[arg(help = "Name or number of the signal, e.g. SIGUSR1, usr1 or 10")]
+    signal: Signal,
+}
+
+#[derive(Debug, Parser)]
+struct RunArgs {
+    project: ActionArg,
+
+    #[arg(last = true, help = "Added to the command of the project")]
+    args: Vec<String>,
+
+    #[arg(
+        short,
+        long,
+        help = "Also write the output to the log of the project. Always done for tasks"
+    )]
+    log: bool,
+}
+
+#[derive(Debug, Parser)]
+struct AttachArgs {
+    project: ActionArg,
+}
+
+#[derive(Debug, Parser)]
+struct StartArgs {
+    #[command(flatten)]
+    action: ActionArgs,
+
+    #[arg(
+        short,
+        long,
+        help = "Wait until the project(s) are healthy, and until tasks have finished"
+    )]
+    wait: bool,
+}
+
 #[derive(Debug, Parser)]
 struct LogsArgs {
-    project: Project,
+    #[command(flatten)]
+    action: ActionArgs,
+
     #[arg(short, long)]
     follow: bool,
 
     #[arg(short, long = "lines", default_value = "50")]
-    number: i32,
+    number: usize,
+
+    #[arg(
+        long,
+        help = "Only print lines after this time, e.g. `10m` or `2024-05-01 12:00:00`"
+    )]
+    since: Option<String>,
+
+    #[arg(
+        long,
+        help = "Only print lines before this time, e.g. `10m` or `2024-05-01 12:00:00`"
+    )]
+    until: Option<String>,
+
+    #[arg(short, long, help = "Only print lines matching the regex")]
+    grep: Option<String>,
+
+    #[arg(short, long, help = "Print the log of the previous run")]
+    previous: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Print the time of each line, when the output is captured"
+    )]
+    timestamps: bool,
+
+    #[arg(long, help = "Only print stderr, when the output is captured")]
+    stderr_only: bool,
+
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 }
 
 #[derive(Debug, Parser)]
 struct StatusArgs {
     #[arg(short, long, help = "Only print name of the project")]
     quiet: bool,
+
+    #[arg(short, long, help = "List the processes of each project as a tree")]
+    tree: bool,
+
+    #[arg(
+        short,
+        long,
+        help = "Also list the projects not running, with how they stopped"
+    )]
+    all: bool,
+
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
 }
 
 #[derive(Debug, Parser)]
 struct ListArgs {
     #[arg(short, long, help = "Only print name of the project")]
     quiet: bool,
+
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Debug, Parser)]
+struct DaemonArgs {
+    #[command(subcommand)]
+    command: DaemonCommands,
+}
+
+#[derive(Debug, Parser)]
+enum DaemonCommands {
+    /// Start the daemon in the background
+    Start {
+        #[arg(long, help = "Run the daemon in the foreground")]
+        foreground: bool,
+    },
+    /// Stop the daemon. Projects started by the daemon keep running
+    Stop,
+    /// Print out the projects supervised by the daemon
+    Status,
 }
 
 #[derive(Parser, Debug)]
+#[allow(clippy::large_enum_variant)]
 enum SubCommands {
     /// Start the specified project(s). E.g. `worker start foo bar`
-    Start(ActionArgs),
+    Start(StartArgs),
     /// Stop the specified project(s). E.g. `worker stop foo bar`
-    Stop(ActionArgs),
+    Stop(StopArgs),
     /// Restart the specified project(s). E.g. `worker restart foo bar` (Same as running stop and then start)
-    Restart(ActionArgs),
-    /// Print out logs for the specified project.
+    Restart(StartArgs),
+    /// Run the specified project in the foreground, and exit the way it does. E.g. `worker run foo -- --verbose`
+    Run(RunArgs),
+    /// Connect the terminal to the input and output of the specified project. E.g. `worker attach foo`
+    Attach(AttachArgs),
+    /// Send a signal to the specified project or group. E.g. `worker signal foo usr1`
+    Signal(SignalArgs),
+    /// Send SIGHUP to the specified project(s), to have them reload their config. E.g. `worker reload foo bar`
+    Reload(ActionArgs),
+    /// Start the specified project(s), and restart them when their files change. E.g. `worker watch foo`
+    Watch(ActionArgs),
+    /// Print out logs for the specified project(s). E.g. `worker logs -f foo bar`
     Logs(LogsArgs),
     /// Print out a status of which projects is running
     Status(StatusArgs),
     /// Print out a list of available projects to run
     List(ListArgs),
+    /// Manage the daemon supervising the projects in this config directory
+    Daemon(DaemonArgs),
+    /// Full screen dashboard to follow and control the projects
+    Ui,
+    /// Check the config for problems, and print where they are
+    #[command(alias = "check")]
+    Validate,
 }
 
 #[derive(Parser, Debug)]
@@ -210,13 +811,18 @@ struct Cli {
 fn main() -> Result<(), anyhow::Error> {
     let cli = Cli::parse();
 
+    // The config is not loaded, since it is what is being checked
+    if let SubCommands::Validate = cli.subcommand {
+        return validate::run();
+    }
+
     let config = WorkerConfig::new()?;
 
     let unique = |projects: Vec<ActionArg>| {
         projects
             .into_iter()
             .flat_map(|it| match it {
-                ActionArg::Project(project) => vec![project],
+                ActionArg::Project(project) => vec![*project],
                 ActionArg::Group(vec) => vec,
             })
             .unique()
@@ -224,12 +830,33 @@ fn main() -> Result<(), anyhow::Error> {
     };
 
     match cli.subcommand {
-        SubCommands::Start(args) => start(&config, unique(args.projects))?,
-        SubCommands::Stop(args) => stop(&config, unique(args.projects))?,
-        SubCommands::Restart(args) => restart(&config, unique(args.projects))?,
-        SubCommands::Logs(args) => logs(&config, args)?,
+        SubCommands::Start(args) => start(&config, unique(args.action.projects), args.wait)?,
+        SubCommands::Stop(args) => stop(&config, unique(args.action.projects), args.force)?,
+        SubCommands::Restart(args) => restart(&config, unique(args.action.projects), args.wait)?,
+        SubCommands::Run(args) => match args.project {
+            ActionArg::Project(project) => {
+                match run::run(&config, *project, &args.args, args.log)? {}
+            }
+            ActionArg::Group(_) => return Err(anyhow!("Only a single project can be run")),
+        },
+        SubCommands::Attach(args) => match args.project {
+            ActionArg::Project(project) => attach::run(&config, *project)?,
+            ActionArg::Group(_) => return Err(anyhow!("Only a single project can be attached to")),
+        },
+        SubCommands::Signal(args) => signal(&config, unique(vec![args.project]), args.signal)?,
+        SubCommands::Reload(args) => signal(&config, unique(args.projects), Signal::SIGHUP)?,
+        SubCommands::Watch(args) => watch::run(&config, unique(args.projects))?,
+        SubCommands::Logs(args) => logs(&config, unique(args.action.projects.clone()), args)?,
         SubCommands::Status(args) => status(&config, args)?,
         SubCommands::List(args) => list(&config, args)?,
+        SubCommands::Daemon(args) => match args.command {
+            DaemonCommands::Start { foreground: true } => daemon::run(config)?,
+            DaemonCommands::Start { foreground: false } => daemon::start(config)?,
+            DaemonCommands::Stop => daemon::stop(&config)?,
+            DaemonCommands::Status => daemon::status(&config)?,
+        },
+        SubCommands::Ui => ui::run(&config)?,
+        SubCommands::Validate => unreachable!(),
     }
 
     Ok(())
@@ -0,0 +1,145 @@
+use std::{
+    convert::Infallible,
+    fs::{File, OpenOptions},
+    io::{Read, Write},
+    os::unix::process::ExitStatusExt,
+    process::Stdio,
+    sync::Mutex,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    config::{Project, ProjectKind, RunningProject, WorkerConfig},
+    libc::{forward_signals, forward_to, ignore_signal, raise, Signal},
+    logs::{self, Stream},
+    shim,
+};
+
+/// Run the project in the foreground, with the same command, envs and cwd as `worker start`,
+/// and `args` added to the command. Signals sent to worker are forwarded to the project. With
+/// `log`, and always for tasks, the output is written to the log of the project as well. The
+/// project is tracked like a started one while it runs. Exits the same way as the project
+pub fn run(
+    config: &WorkerConfig,
+    project: Project,
+    args: &[String],
+    log: bool,
+) -> Result<Infallible, anyhow::Error> {
+    let log = log || project.kind == ProjectKind::Task;
+
+    let lock = config.lock(&project)?;
+    if config.is_running(&project)? {
+        return Err(anyhow!("{} is already running", project));
+    }
+    let mut cmd = config.bare_command(&project)?;
+    cmd.args(args).stdin(Stdio::inherit());
+
+    let mut pipes = Vec::new();
+    if log {
+        logs::rotate_on_start(config, &project)?;
+        let (stdout, stdout_writer) = std::io::pipe()?;
+        let (stderr, stderr_writer) = std::io::pipe()?;
+        cmd.stdout(stdout_writer).stderr(stderr_writer);
+        pipes.push((stdout, Stream::Stdout));
+        pipes.push((stderr, Stream::Stderr));
+    }
+
+    forward_signals(&[
+        Signal::SIGHUP,
+        Signal::SIGTERM,
+        Signal::SIGUSR1,
+        Signal::SIGUSR2,
+        Signal::SIGALRM,
+    ]);
+    let mut child = cmd.spawn()?;
+    let pid = child.id() as i32;
+    forward_to(pid);
+    // The command holds on to the write ends, which would keep the pipes from being closed
+    drop(cmd);
+    config.store_state(&RunningProject {
+        foreground: true,
+        ..RunningProject::new(project.clone(), pid)
+    })?;
+    config.record_start(&project, pid)?;
+    drop(lock);
+
+    // The project is in the foreground of the terminal as well, so Ctrl-C reaches it without
+    // being forwarded. It decides when to exit, and its output is printed until then
+    ignore_signal(Signal::SIGINT);
+    ignore_signal(Signal::SIGQUIT);
+
+    if log {
+        let capture = project.capture();
+        let file = Mutex::new(
+            OpenOptions::new()
+                .append(true)
+                .create(true)
+                .open(config.log_file(&project))?,
+        );
+        std::thread::scope(|scope| {
+            let file = &file;
+            for (pipe, stream) in pipes {
+                scope.spawn(move || match stream {
+                    Stream::Stdout => tee(pipe, stream, std::io::stdout(), file, capture),
+                    Stream::Stderr => tee(pipe, stream, std::io::stderr(), file, capture),
+                });
+            }
+        });
+    }
+
+    let status = child.wait()?;
+    config.record_exit(&project, pid, Some(status.into()))?;
+    config.remove_state(pid, &project)?;
+
+    if let Some(signal) = status.signal() {
+        raise(signal);
+    }
+
+    std::process::exit(status.code().unwrap_or(1))
+}
+
+// Print the output as it comes, and write it to the log. Captured output is written to the log
+// a line at a time, with the time and the stream of each line
+fn tee(
+    mut pipe: impl Read,
+    stream: Stream,
+    mut out: impl Write,
+    log: &Mutex<File>,
+    capture: bool,
+) -> Result<(), anyhow::Error> {
+    let mut buf = [0; 8192];
+    let mut line = Vec::new();
+    loop {
+        let n = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+
+        // Keep going when the output is closed, to not block the task
+        let _ = out.write_all(&buf[..n]).and_then(|_| out.flush());
+
+        if !capture {
+            log.lock().unwrap().write_all(&buf[..n])?;
+            continue;
+        }
+
+        line.extend_from_slice(&buf[..n]);
+        while let Some(i) = line.iter().position(|it| *it == b'\n') {
+            let rest = line.split_off(i + 1);
+            line.pop();
+            let captured = shim::captured_line(stream, &line);
+            log.lock().unwrap().write_all(captured.as_bytes())?;
+            line = rest;
+        }
+    }
+
+    if !line.is_empty() {
+        let captured = shim::captured_line(stream, &line);
+        log.lock().unwrap().write_all(captured.as_bytes())?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::libc::{localtime, mktime, LocalTime};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+// Limits the search for the next run of schedules that never match, like February 30th
+const MAX_STEPS: usize = 100_000;
+
+/// A cron expression with the fields `minute hour day-of-month month day-of-week`, e.g.
+/// `*/15 * * * *`. Fields accept `*`, numbers, ranges like `1-5`, lists like `1,15` and steps
+/// like `*/15` or `0-30/10`. Months and weekdays can be given by their first three letters, and
+/// Sunday is both 0 and 7. `@hourly`, `@daily`, `@weekly`, `@monthly` and `@yearly` are short
+/// for the common schedules
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Schedule {
+    expression: String,
+    // The values each field matches, as bit sets
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // When both the day of the month and the day of the week are restricted, a day matching
+    // either of them is enough
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// The first time after `time` the schedule matches in the local time zone. Both are unix
+    /// times in seconds. `None` if the schedule never matches
+    pub fn next_after(&self, time: u64) -> Option<u64> {
+        let has = |bits: u64, value: i32| bits & (1 << value) != 0;
+
+        // Runs start on whole minutes
+        let mut time = (time / 60 + 1) * 60;
+        for _ in 0..MAX_STEPS {
+            let local = localtime(time as i64)?;
+            let day = has(self.days, local.day);
+            let weekday = has(self.weekdays, local.weekday);
+            let day = match (self.any_day, self.any_weekday) {
+                (false, false) => day || weekday,
+                _ => day && weekday,
+            };
+
+            let next = if !has(self.months, local.month) {
+                LocalTime {
+                    month: local.month + 1,
+                    day: 1,
+                    hour: 0,
+                    minute: 0,
+                    ..local
+                }
+            } else if !day {
+                LocalTime {
+                    day: local.day + 1,
+                    hour: 0,
+                    minute: 0,
+                    ..local
+                }
+            } else if !has(self.hours, local.hour) {
+                LocalTime {
+                    hour: local.hour + 1,
+                    minute: 0,
+                    ..local
+                }
+            } else if !has(self.minutes, local.minute) {
+                LocalTime {
+                    minute: local.minute + 1,
+                    ..local
+                }
+            } else {
+                return Some(time);
+            };
+
+            // The same local time can come twice when daylight saving time ends
+            time = (mktime(&next)? as u64).max(time + 60);
+        }
+
+        None
+    }
+}
+
+impl std::fmt::Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = match s.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expression => expression,
+        };
+
+        let fields: Vec<_> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(anyhow!(
+                "Invalid schedule {}: expected 5 fields, minute hour day month weekday",
+                s
+            ));
+        };
+
+        let parse = |field: &str, min: u32, max: u32, names: &[&str]| {
+            parse_field(field, min, max, names).with_context(|| format!("Invalid schedule {}", s))
+        };
+        let weekdays = parse(weekday, 0, 7, &WEEKDAYS)?;
+
+        Ok(Self {
+            expression: s.trim().to_string(),
+            minutes: parse(minute, 0, 59, &[])?,
+            hours: parse(hour, 0, 23, &[])?,
+            days: parse(day, 1, 31, &[])?,
+            months: parse(month, 1, 12, &MONTHS)?,
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+}
+
+// The values matched by one field of the expression, as a bit set
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, anyhow::Error> {
+    let value = |value: &str| {
+        let number = names
+            .iter()
+            .position(|it| it.eq_ignore_ascii_case(value))
+            .map(|i| i as u32 + min)
+            .or_else(|| value.parse().ok())
+            .with_context(|| format!("{} is not a valid value", value))?;
+        match (min..=max).contains(&number) {
+            true => Ok(number),
+            false => Err(anyhow!("{} is not between {} and {}", number, min, max)),
+        }
+    };
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (
+                range,
+                step.parse::<usize>()
+                    .ok()
+                    .filter(|it| *it > 0)
+                    .with_context(|| format!("{} is not a valid step", step))?,
+            ),
+            None => (part, 1),
+        };
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            // A step from a single value goes on to the end, e.g. `5/15`
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start > end {
+            return Err(anyhow!("{} is not a valid range", range));
+        }
+
+        for value in (start..=end).step_by(step) {
+            bits |= 1 << value;
+        }
+    }
+
+    Ok(bits)
+}
+
+impl Serialize for Schedule {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.expression)
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(|e| serde::de::Error::custom(format!("{:#}", e)))
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    convert::Infallible,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Read, Write},
+    os::{
+        fd::{AsRawFd, OwnedFd},
+        unix::process::{CommandExt, ExitStatusExt},
+    },
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use ::libc::EIO;
+use anyhow::anyhow;
+
+use crate::{
+    config::{Project, StdinMode, WorkerConfig},
+    libc::{dup2, getsid, ignore_stop_signals, openpty, raise, reset_stop_signals},
+    logs::{self, Stream},
+};
+
+// The window size of the tty. Wide enough for most log lines to not be wrapped
+const TTY_ROWS: u16 = 24;
+const TTY_COLUMNS: u16 = 120;
+
+// How often the size of the log is checked, the same as the daemon does
+const ROTATE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Run the project as a child, and stay in front of it until it exits. Used when the output is
+/// captured, to write every line to the log with the time and the stream it was written to, when
+/// the project runs in a tty, to copy its output to the log, and when nobody else is around to
+/// `record` how the project exited. In that case it also rotates the log when it grows too
+/// large, as the daemon would. Signals to stop the project are ignored, to keep going until the
+/// project has exited. Then exits the same way as the project
+pub fn run(
+    config: &WorkerConfig,
+    project: &Project,
+    mut cmd: Command,
+    record: bool,
+) -> Result<Infallible, anyhow::Error> {
+    let capture = project.capture();
+    let mut pipes: Vec<(File, Stream)> = Vec::new();
+    if project.tty() {
+        // Both streams go to the terminal, so they can't be told apart
+        let (terminal, tty) = openpty(TTY_ROWS, TTY_COLUMNS)
+            .map_err(|_| anyhow!("Couldn't open a tty: {}", std::io::Error::last_os_error()))?;
+        cmd.stdout(tty.try_clone()?).stderr(tty.try_clone()?);
+        if project.stdin == StdinMode::Pipe {
+            let input = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(config.stdin_file(project))?;
+            let terminal = File::from(terminal.try_clone()?);
+            // Never ends, since the pipe is open for writing as well
+            std::thread::spawn(move || std::io::copy(&mut &input, &mut &terminal));
+            cmd.stdin(tty);
+        }
+        pipes.push((terminal.into(), Stream::Stdout));
+    } else if capture {
+        let (stdout, stdout_writer) = std::io::pipe()?;
+        let (stderr, stderr_writer) = std::io::pipe()?;
+        cmd.stdout(stdout_writer).stderr(stderr_writer);
+        pipes.push((OwnedFd::from(stdout).into(), Stream::Stdout));
+        pipes.push((OwnedFd::from(stderr).into(), Stream::Stderr));
+    }
+
+    // Stopping the project stops the shim as well, and it has to be around to see the project
+    // exit. Only the project gets the default handlers back
+    ignore_stop_signals();
+    unsafe {
+        cmd.pre_exec(|| {
+            reset_stop_signals();
+            Ok(())
+        })
+    };
+    let mut child = cmd.spawn()?;
+
+    // The command holds on to the write ends, which would keep the pipes and the tty from being
+    // closed
+    drop(cmd);
+
+    let log = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(config.log_file(project))?;
+
+    // Let go of the stdio of whoever started the project, as they may be waiting for it to close
+    let null = File::open("/dev/null")?;
+    for (src, dst) in [
+        (null.as_raw_fd(), 0),
+        (log.as_raw_fd(), 1),
+        (log.as_raw_fd(), 2),
+    ] {
+        dup2(src, dst).map_err(|_| anyhow!("Couldn't redirect the output"))?;
+    }
+
+    // Without the daemon, nobody else keeps the log from growing past its max size
+    let rotate = record && project.log.as_ref().is_some_and(|it| it.max_size.is_some());
+    let exited = AtomicBool::new(false);
+
+    let log = Mutex::new(log);
+    let status = std::thread::scope(|scope| {
+        if rotate {
+            scope.spawn(|| {
+                while !exited.load(Ordering::Relaxed) {
+                    if let Err(e) = logs::rotate_if_too_large(config, project) {
+                        eprintln!("Couldn't rotate log of {}: {}", project, e);
+                    }
+                    std::thread::sleep(ROTATE_INTERVAL);
+                }
+            });
+        }
+
+        for (pipe, stream) in pipes {
+            let log = &log;
+            scope.spawn(move || match capture {
+                true => relay(Output(pipe), stream, log),
+                false => std::io::copy(&mut Output(pipe), &mut &*log.lock().unwrap())
+                    .map(|_| ())
+                    .map_err(Into::into),
+            });
+        }
+
+        let status = child.wait();
+        exited.store(true, Ordering::Relaxed);
+        status
+    })?;
+    if record {
+        let sid = getsid(0).map_err(|_| anyhow!("Couldn't get the session id"))?;
+        config.record_exit(project, sid, Some(status.into()))?;
+    }
+
+    if let Some(signal) = status.signal() {
+        raise(signal);
+    }
+
+    std::process::exit(status.code().unwrap_or(1))
+}
+
+// Reads the output of the project until it is closed. The other side of a tty being closed is an
+// error rather than the end of the file
+struct Output(File);
+
+impl Read for Output {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.raw_os_error() == Some(EIO) => Ok(0),
+            res => res,
+        }
+    }
+}
+
+fn relay(pipe: impl Read, stream: Stream, log: &Mutex<File>) -> Result<(), anyhow::Error> {
+    for line in BufReader::new(pipe).split(b'\n') {
+        let line = captured_line(stream, &line?);
+        log.lock().unwrap().write_all(line.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// A line of captured output as it is written to the log, with the time and the stream
+pub fn captured_line(stream: Stream, line: &[u8]) -> String {
+    format!(
+        "{} {} {}\n",
+        humantime::format_rfc3339_millis(SystemTime::now()),
+        stream,
+        String::from_utf8_lossy(line)
+    )
+}
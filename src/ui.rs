@@ -0,0 +1,428 @@
+use std::{
+    collections::VecDeque,
+    io::{BufRead, BufReader, IsTerminal},
+    os::fd::{AsFd, AsRawFd},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+        LazyLock,
+    },
+    thread::Scope,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Table, TableState},
+    DefaultTerminal, Frame,
+};
+use regex::Regex;
+
+use crate::{
+    config::{Project, WorkerConfig},
+    format::{self, ProjectStatus, State},
+    libc::dup2,
+    logs::{LogFilter, LogReader},
+};
+
+const REFRESH: Duration = Duration::from_secs(1);
+const TICK: Duration = Duration::from_millis(100);
+
+// Lines kept in the log pane
+const LOG_LINES: usize = 1000;
+
+// Escape sequences would mess up the screen, e.g. colors in the log
+static ESCAPE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b(\[[0-9;?]*[A-Za-z]|\][^\x07]*\x07)").unwrap());
+
+enum Message {
+    Statuses(Vec<ProjectStatus>),
+    Info(String),
+}
+
+#[derive(Clone, Copy)]
+enum Action {
+    Start,
+    Stop,
+    Restart,
+}
+
+struct Ui<'a> {
+    config: &'a WorkerConfig,
+    statuses: Vec<ProjectStatus>,
+    table: TableState,
+    groups: Vec<String>,
+    // Only the projects in this group are listed
+    group: Option<usize>,
+    log: Option<(String, LogReader)>,
+    lines: VecDeque<String>,
+    message: String,
+}
+
+/// Run the dashboard until the user quits. The status of the projects is refreshed in the
+/// background, and actions run in the background too, to keep the screen responsive
+pub fn run(config: &WorkerConfig) -> Result<(), anyhow::Error> {
+    if !std::io::stdout().is_terminal() {
+        return Err(anyhow!("worker ui needs to run in a terminal"));
+    }
+
+    let (tx, rx) = mpsc::channel();
+
+    // Whatever the actions print to stderr is shown as a message instead of on top of the screen
+    let (stderr, writer) = std::io::pipe()?;
+    let saved = std::io::stderr().as_fd().try_clone_to_owned()?;
+    dup2(writer.as_raw_fd(), 2).map_err(|_| anyhow!("Couldn't redirect stderr"))?;
+    drop(writer);
+    let messages = tx.clone();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            let _ = messages.send(Message::Info(line));
+        }
+    });
+
+    let done = AtomicBool::new(false);
+    let mut terminal = ratatui::init();
+    let res = std::thread::scope(|scope| {
+        scope.spawn(|| refresh(config, &tx, &done));
+
+        let res = Ui::new(config).run(&mut terminal, scope, &tx, &rx);
+        done.store(true, Ordering::Relaxed);
+        res
+    });
+    ratatui::restore();
+
+    dup2(saved.as_raw_fd(), 2).map_err(|_| anyhow!("Couldn't restore stderr"))?;
+
+    res
+}
+
+fn refresh(config: &WorkerConfig, tx: &Sender<Message>, done: &AtomicBool) {
+    while !done.load(Ordering::Relaxed) {
+        let message = match config.running() {
+            Ok(running) => Message::Statuses(crate::statuses(config, running, true)),
+            Err(e) => Message::Info(e.to_string()),
+        };
+        if tx.send(message).is_err() {
+            return;
+        }
+
+        let start = Instant::now();
+        while Instant::now().duration_since(start) < REFRESH && !done.load(Ordering::Relaxed) {
+            std::thread::sleep(TICK);
+        }
+    }
+}
+
+impl<'a> Ui<'a> {
+    fn new(config: &'a WorkerConfig) -> Self {
+        let groups = config
+            .projects
+            .iter()
+            .flat_map(|it| it.group.iter().flatten())
+            .fold(Vec::new(), |mut groups, group| {
+                if !groups.contains(group) {
+                    groups.push(group.clone());
+                }
+                groups
+            });
+
+        Self {
+            config,
+            statuses: Vec::new(),
+            table: TableState::default().with_selected(0),
+            groups,
+            group: None,
+            log: None,
+            lines: VecDeque::new(),
+            message: String::new(),
+        }
+    }
+
+    fn run<'scope>(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        scope: &'scope Scope<'scope, '_>,
+        tx: &Sender<Message>,
+        rx: &mpsc::Receiver<Message>,
+    ) -> Result<(), anyhow::Error>
+    where
+        'a: 'scope,
+    {
+        loop {
+            for message in rx.try_iter() {
+                match message {
+                    Message::Statuses(statuses) => self.statuses = statuses,
+                    Message::Info(info) => self.message = info,
+                }
+            }
+            self.follow_log()?;
+
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if !event::poll(TICK)? {
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            let all = self.visible();
+            let selected: Vec<_> = self.selected().into_iter().collect();
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Down | KeyCode::Char('j') => self.select(1),
+                KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+                KeyCode::Char('g') => {
+                    self.group = match self.group {
+                        None if !self.groups.is_empty() => Some(0),
+                        Some(i) if i + 1 < self.groups.len() => Some(i + 1),
+                        _ => None,
+                    };
+                    self.table.select(Some(0));
+                }
+                KeyCode::Char('s') => self.act(scope, tx, Action::Start, selected),
+                KeyCode::Char('x') => self.act(scope, tx, Action::Stop, selected),
+                KeyCode::Char('r') => self.act(scope, tx, Action::Restart, selected),
+                KeyCode::Char('S') => self.act(scope, tx, Action::Start, all),
+                KeyCode::Char('X') => self.act(scope, tx, Action::Stop, all),
+                KeyCode::Char('R') => self.act(scope, tx, Action::Restart, all),
+                _ => {}
+            }
+        }
+    }
+
+    // The projects in the selected group, or every project
+    fn visible(&self) -> Vec<Project> {
+        let group = self.group.map(|i| &self.groups[i]);
+        self.config
+            .projects
+            .iter()
+            .filter(|it| group.is_none_or(|group| it.group.iter().flatten().any(|g| g == group)))
+            .cloned()
+            .collect()
+    }
+
+    fn selected(&self) -> Option<Project> {
+        self.visible().into_iter().nth(self.table.selected()?)
+    }
+
+    fn select(&mut self, offset: isize) {
+        let len = self.visible().len();
+        if len == 0 {
+            return;
+        }
+
+        let selected = self.table.selected().unwrap_or(0) as isize;
+        let selected = (selected + offset).rem_euclid(len as isize);
+        self.table.select(Some(selected as usize));
+    }
+
+    fn act<'scope>(
+        &mut self,
+        scope: &'scope Scope<'scope, '_>,
+        tx: &Sender<Message>,
+        action: Action,
+        projects: Vec<Project>,
+    ) where
+        'a: 'scope,
+    {
+        if projects.is_empty() {
+            return;
+        }
+
+        let tx = tx.clone();
+        let names = projects
+            .iter()
+            .map(|it| it.name.clone())
+            .collect::<Vec<_>>();
+        let (command, doing, done) = match action {
+            Action::Start => ("start", "Starting", "Started"),
+            Action::Stop => ("stop", "Stopping", "Stopped"),
+            Action::Restart => ("restart", "Restarting", "Restarted"),
+        };
+        self.message = format!("{} {}", doing, names.join(", "));
+
+        // Starting projects forks without the daemon, which is not safe with the threads of
+        // the ui around, so the actions are run by another worker
+        scope.spawn(move || {
+            let res = std::env::current_exe().and_then(|exe| {
+                Command::new(exe)
+                    .arg(command)
+                    .args(&names)
+                    .env("RUST_BACKTRACE", "0")
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::piped())
+                    .output()
+            });
+            let message = match res {
+                Ok(output) if output.status.success() => {
+                    format!("{} {}", done, names.join(", "))
+                }
+                // The error is printed last
+                Ok(output) => String::from_utf8_lossy(&output.stderr)
+                    .lines()
+                    .last()
+                    .map(|it| it.trim_start_matches("Error: ").to_string())
+                    .unwrap_or_else(|| format!("Couldn't {} {}", command, names.join(", "))),
+                Err(e) => e.to_string(),
+            };
+            let _ = tx.send(Message::Info(message));
+        });
+    }
+
+    // Read the new lines of the log of the selected project
+    fn follow_log(&mut self) -> Result<(), anyhow::Error> {
+        let Some(project) = self.selected() else {
+            self.log = None;
+            self.lines.clear();
+            return Ok(());
+        };
+
+        let lines = match self.log {
+            Some((ref name, ref mut reader)) if *name == project.name => {
+                reader.poll(&LogFilter::default())?
+            }
+            _ => {
+                let mut reader = LogReader::new(self.config.log_file(&project));
+                let lines = reader.tail(LOG_LINES, &LogFilter::default())?;
+                self.log = Some((project.name.clone(), reader));
+                self.lines.clear();
+                lines
+            }
+        };
+
+        for line in lines {
+            if self.lines.len() == LOG_LINES {
+                self.lines.pop_front();
+            }
+            let text = ESCAPE.replace_all(&line.text, "").replace('\t', "    ");
+            self.lines.push_back(text);
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [projects, log, footer] = Layout::vertical([
+            Constraint::Percentage(40),
+            Constraint::Min(5),
+            Constraint::Length(2),
+        ])
+        .areas(frame.area());
+
+        let rows: Vec<_> = self
+            .visible()
+            .iter()
+            .map(|project| {
+                let status = self
+                    .statuses
+                    .iter()
+                    .find(|it| it.project().name == project.name);
+                row(project, status)
+            })
+            .collect();
+
+        let title = match self.group {
+            Some(i) => format!(" Projects in {} ", self.groups[i]),
+            None => " Projects ".to_string(),
+        };
+        let header = Row::new([
+            "NAME", "STATE", "SID", "UPTIME", "CPU", "RSS", "HEALTH", "GROUPS",
+        ])
+        .style(Style::new().add_modifier(Modifier::BOLD));
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(2),
+                Constraint::Length(8),
+                Constraint::Length(8),
+                Constraint::Length(12),
+                Constraint::Length(7),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(header)
+        .block(Block::bordered().title(title))
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, projects, &mut self.table);
+
+        // Only the last lines fitting in the pane are shown
+        let height = log.height.saturating_sub(2) as usize;
+        let lines: Vec<_> = self
+            .lines
+            .iter()
+            .skip(self.lines.len().saturating_sub(height))
+            .map(|it| Line::raw(it.as_str()))
+            .collect();
+        let title = match self.log {
+            Some((ref name, _)) => format!(" Log of {} ", name),
+            None => " Log ".to_string(),
+        };
+        frame.render_widget(
+            Paragraph::new(lines).block(Block::bordered().title(title)),
+            log,
+        );
+
+        let help = "↑/↓ select  s/x/r start/stop/restart  S/X/R all listed  g group  q quit";
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::raw(self.message.as_str()),
+                Line::styled(help, Style::new().fg(Color::DarkGray)),
+            ]),
+            footer,
+        );
+    }
+}
+
+fn row<'a>(project: &Project, status: Option<&ProjectStatus>) -> Row<'a> {
+    let name = project.display.clone().unwrap_or(project.name.clone());
+    let groups = project.group.clone().unwrap_or_default().join(",");
+
+    let Some(status) = status else {
+        return Row::new([name, String::new(), String::new()]);
+    };
+
+    let color = match status.state() {
+        State::Running => Color::Green,
+        State::Stopped => Color::DarkGray,
+        State::Exited => Color::Yellow,
+        State::Crashed => Color::Red,
+    };
+
+    let cells = match status {
+        ProjectStatus::Running(it) => vec![
+            name,
+            it.state.to_string(),
+            it.sid.to_string(),
+            it.uptime_secs.map_or(String::new(), |it| {
+                humantime::format_duration(Duration::from_secs(it)).to_string()
+            }),
+            format!("{:.1}%", it.cpu_percent),
+            format::format_bytes(it.rss_bytes),
+            it.health.map_or(String::new(), |it| it.to_string()),
+            groups,
+        ],
+        ProjectStatus::Stopped(it) => {
+            let mut cells = vec![name, it.state.to_string()];
+            cells.extend(std::iter::repeat_n(String::new(), 5));
+            cells.push(groups);
+            cells
+        }
+    };
+
+    Row::new(cells).style(Style::new().fg(color))
+}
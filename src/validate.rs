@@ -0,0 +1,449 @@
+use std::{
+    collections::HashSet,
+    ops::Range,
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use toml_edit::{ImDocument, Item, TableLike, Value};
+
+use crate::{
+    config::{self, Config, ConfigError, ConfigFile, LogConfig, Project, ProjectKind},
+    libc::Signal,
+    schedule::Schedule,
+};
+
+/// Check the config for problems, and print every problem found with the file and line it is at
+pub fn run() -> Result<(), anyhow::Error> {
+    let base_dir = config::find_config_dir()?.context("Couldn't find config dir")?;
+    let base_dir = base_dir.canonicalize()?;
+
+    let problems = match config::config_files(&base_dir) {
+        Ok(files) => Validator::new(&base_dir, &files).validate(),
+        Err(e) => match e.downcast::<ConfigError>() {
+            Ok(e) => vec![e],
+            Err(e) => vec![ConfigError {
+                path: base_dir.join(config::CONFIG_FILE),
+                line: None,
+                message: format!("{:#}", e),
+            }],
+        },
+    };
+
+    if problems.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+
+    for problem in problems.iter() {
+        let path = problem
+            .path
+            .strip_prefix(&base_dir)
+            .unwrap_or(&problem.path);
+        let problem = ConfigError {
+            path: path.to_path_buf(),
+            line: problem.line,
+            message: problem.message.clone(),
+        };
+        println!("{}", problem);
+    }
+
+    match problems.len() {
+        1 => Err(anyhow!("Found 1 problem in the config")),
+        n => Err(anyhow!("Found {} problems in the config", n)),
+    }
+}
+
+struct Validator<'a> {
+    base_dir: &'a Path,
+    files: &'a [ConfigFile],
+    documents: Vec<Option<ImDocument<&'a str>>>,
+    problems: Vec<ConfigError>,
+}
+
+impl<'a> Validator<'a> {
+    fn new(base_dir: &'a Path, files: &'a [ConfigFile]) -> Self {
+        Self {
+            base_dir,
+            files,
+            documents: files
+                .iter()
+                .map(|it| ImDocument::parse(it.content.as_str()).ok())
+                .collect(),
+            problems: Vec::new(),
+        }
+    }
+
+    fn validate(mut self) -> Vec<ConfigError> {
+        self.duplicate_projects();
+
+        let mut config = toml::Table::new();
+        // Projects defined more than once are reported above, with the lines they are at
+        for file in self.files {
+            let _ = config::merge(&mut config, file.table.clone());
+        }
+
+        let tables = match config.insert("project".to_string(), toml::Value::Array(Vec::new())) {
+            Some(toml::Value::Array(projects)) => projects,
+            _ => Vec::new(),
+        };
+
+        // The projects are checked one by one, to find every problem in them
+        let mut unknown = Vec::new();
+        let config: Option<Config> =
+            serde_ignored::deserialize(toml::Value::Table(config), |path| {
+                unknown.push(keys(&path))
+            })
+            .map_err(|e| self.problem(None, &[], e.message().to_string()))
+            .ok();
+        for path in unknown {
+            self.problem(None, &path, format!("Unknown field {}", path.join(".")));
+        }
+
+        let log = config
+            .as_ref()
+            .and_then(|it| it.log.clone())
+            .unwrap_or_default();
+        let env_files = config.and_then(|it| it.env_file).unwrap_or_default();
+
+        let projects: Vec<_> = tables
+            .into_iter()
+            .filter_map(|it| self.project(it, &log, &env_files))
+            .collect();
+
+        for project in projects.iter() {
+            for group in project.group.iter().flatten() {
+                if projects.iter().any(|it| &it.name == group) {
+                    self.problem(
+                        Some(&project.name),
+                        &["group".to_string()],
+                        format!("Group {} has the same name as a project", group),
+                    );
+                }
+            }
+        }
+
+        if let Err(e) = config::validate_dependencies(&projects) {
+            self.problem(None, &[], e.to_string());
+        }
+        if let Err(e) = config::validate_healthchecks(&projects) {
+            self.problem(None, &[], e.to_string());
+        }
+
+        self.problems
+            .sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+        self.problems
+    }
+
+    // Names defined more than once in the same file. Projects with the same name in different
+    // files are merged instead
+    fn duplicate_projects(&mut self) {
+        for (i, document) in self.documents.iter().enumerate() {
+            let mut names = HashSet::new();
+            for project in document.iter().flat_map(|it| projects(it.as_item())) {
+                let Some((key, name)) = project
+                    .get_key_value("name")
+                    .and_then(|(key, it)| Some((key, it.as_str()?)))
+                else {
+                    continue;
+                };
+
+                if !names.insert(name) {
+                    self.problems.push(ConfigError {
+                        path: self.files[i].path.clone(),
+                        line: key
+                            .span()
+                            .map(|it| config::line_of(&self.files[i].content, it.start)),
+                        message: format!("Project {} is defined more than once", name),
+                    });
+                }
+            }
+        }
+    }
+
+    // Check the project as merged from every file, and return it if it can be used to check
+    // the rest of the config
+    fn project(
+        &mut self,
+        mut table: toml::Value,
+        log: &LogConfig,
+        env_files: &[String],
+    ) -> Option<Project> {
+        let Some(name) = table
+            .get("name")
+            .and_then(|it| it.as_str())
+            .map(String::from)
+        else {
+            self.problem(None, &[], "Project without a name".to_string());
+            return None;
+        };
+        // Invalid signals are left out, to be able to check the rest of the project
+        if let Some(signal) = table.get("stop_signal").cloned() {
+            if Signal::deserialize(signal.clone()).is_err() {
+                self.problem(
+                    Some(&name),
+                    &["stop_signal".to_string()],
+                    format!("Invalid signal {}", signal),
+                );
+                table.as_table_mut()?.remove("stop_signal");
+            }
+        }
+        if let Some(Err(e)) = table
+            .get("schedule")
+            .and_then(|it| it.as_str())
+            .map(str::parse::<Schedule>)
+        {
+            self.problem(Some(&name), &["schedule".to_string()], format!("{:#}", e));
+            table.as_table_mut()?.remove("schedule");
+        }
+        if let Some(toml::Value::Array(steps)) = table.get_mut("stop_sequence") {
+            let invalid: Vec<_> = steps
+                .iter()
+                .enumerate()
+                .filter_map(|(i, it)| Some((i, it.get("signal")?.clone())))
+                .filter(|(_, signal)| Signal::deserialize(signal.clone()).is_err())
+                .collect();
+            for (i, signal) in invalid.iter().rev() {
+                steps.remove(*i);
+                let path = [
+                    "stop_sequence".to_string(),
+                    i.to_string(),
+                    "signal".to_string(),
+                ];
+                self.problem(Some(&name), &path, format!("Invalid signal {}", signal));
+            }
+        }
+
+        let mut unknown = Vec::new();
+        let res: Result<Project, _> =
+            serde_ignored::deserialize(table, |path| unknown.push(keys(&path)));
+        for path in unknown {
+            self.problem(
+                Some(&name),
+                &path,
+                format!("Unknown field {}", path.join(".")),
+            );
+        }
+        let mut project = match res {
+            Ok(project) => project,
+            Err(e) => {
+                self.problem(Some(&name), &[], format!("{}: {}", name, e.message()));
+                return None;
+            }
+        };
+
+        if project.schedule.is_some() && project.kind != ProjectKind::Task {
+            self.problem(
+                Some(&name),
+                &["schedule".to_string()],
+                "Only tasks can be scheduled. Set kind = \"task\"".to_string(),
+            );
+        }
+
+        match config::resolve_project(&mut project, self.base_dir, log, env_files) {
+            Ok(unset) => {
+                for variable in unset {
+                    self.problem(
+                        Some(&name),
+                        &variable.field,
+                        format!(
+                            "Variable {0} is not set, and is left as ${{{0}}}. Use $${{{0}}} if it is meant for the shell, or ${{{0}:-}} to leave it empty",
+                            variable.name
+                        ),
+                    );
+                }
+            }
+            Err(e) => {
+                self.problem(Some(&name), &[], format!("{}: {:#}", name, e));
+                return Some(project);
+            }
+        }
+
+        let command = ["command".to_string()];
+        match shlex::split(&project.command) {
+            None => self.problem(
+                Some(&name),
+                &command,
+                format!("Couldn't parse command: {}", project.command),
+            ),
+            Some(parts) if parts.is_empty() => {
+                self.problem(Some(&name), &command, "Empty command".to_string())
+            }
+            Some(parts) => {
+                if let Err(e) = find_executable(&project, &parts[0]) {
+                    self.problem(Some(&name), &command, e.to_string());
+                }
+            }
+        }
+
+        if !Path::new(&project.cwd).is_dir() {
+            self.problem(
+                Some(&name),
+                &["cwd".to_string()],
+                format!("{} is not a directory", project.cwd),
+            );
+        }
+
+        for path in project
+            .watch
+            .iter()
+            .flat_map(|it| it.paths.iter().flatten())
+        {
+            if !Path::new(&project.cwd).join(path).exists() {
+                self.problem(
+                    Some(&name),
+                    &["watch".to_string(), "paths".to_string()],
+                    format!("Watched path {} does not exist", path),
+                );
+            }
+        }
+        for pattern in project
+            .watch
+            .iter()
+            .flat_map(|it| it.ignore.iter().flatten())
+        {
+            if let Err(e) = glob::Pattern::new(pattern) {
+                self.problem(
+                    Some(&name),
+                    &["watch".to_string(), "ignore".to_string()],
+                    format!("Invalid ignore pattern {}: {}", pattern, e.msg),
+                );
+            }
+        }
+
+        Some(project)
+    }
+
+    // Add a problem at the field of the project, or at the top level if `project` is None. The
+    // last file setting the field is used, since that is the value in effect. Otherwise the
+    // problem is put where the project is first defined
+    fn problem(&mut self, project: Option<&str>, path: &[String], message: String) {
+        let mut at_field = None;
+        let mut at_project = None;
+        for (i, document) in self.documents.iter().enumerate() {
+            let Some(document) = document else {
+                continue;
+            };
+
+            let tables: Vec<&dyn TableLike> = match project {
+                None => vec![document.as_table()],
+                Some(name) => projects(document.as_item())
+                    .into_iter()
+                    .filter(|it| it.get("name").and_then(|it| it.as_str()) == Some(name))
+                    .collect(),
+            };
+            for table in tables {
+                if let Some(span) = locate(table, path) {
+                    at_field = Some((i, span));
+                }
+                if at_project.is_none() {
+                    at_project = name_span(table).map(|it| (i, it));
+                }
+            }
+        }
+
+        let problem = match at_field.or(at_project) {
+            Some((i, span)) => ConfigError {
+                path: self.files[i].path.clone(),
+                line: Some(config::line_of(&self.files[i].content, span.start)),
+                message,
+            },
+            None => ConfigError {
+                path: self.base_dir.join(config::CONFIG_FILE),
+                line: None,
+                message,
+            },
+        };
+        self.problems.push(problem);
+    }
+}
+
+// The keys of a path reported by serde_ignored, leaving out options and newtypes
+fn keys(path: &serde_ignored::Path) -> Vec<String> {
+    match path {
+        serde_ignored::Path::Root => Vec::new(),
+        serde_ignored::Path::Seq { parent, index } => {
+            let mut keys = keys(parent);
+            keys.push(index.to_string());
+            keys
+        }
+        serde_ignored::Path::Map { parent, key } => {
+            let mut keys = keys(parent);
+            keys.push(key.clone());
+            keys
+        }
+        serde_ignored::Path::Some { parent }
+        | serde_ignored::Path::NewtypeStruct { parent }
+        | serde_ignored::Path::NewtypeVariant { parent } => keys(parent),
+    }
+}
+
+// The tables of the projects in a file, both as `[[project]]` and `project = [{ ... }]`
+fn projects(document: &Item) -> Vec<&dyn TableLike> {
+    match document.get("project") {
+        Some(Item::ArrayOfTables(tables)) => tables.iter().map(|it| it as &dyn TableLike).collect(),
+        Some(Item::Value(Value::Array(array))) => array
+            .iter()
+            .filter_map(|it| it.as_inline_table())
+            .map(|it| it as &dyn TableLike)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn name_span(table: &dyn TableLike) -> Option<Range<usize>> {
+    table.get_key_value("name").and_then(|(key, _)| key.span())
+}
+
+// The span of the key at `path`, or of the closest parent of it that exists
+fn locate(table: &dyn TableLike, path: &[String]) -> Option<Range<usize>> {
+    let (key, rest) = path.split_first()?;
+    let (key, item) = table.get_key_value(key)?;
+
+    let index = rest.first().and_then(|it| it.parse::<usize>().ok());
+    let found = match (item, index) {
+        (Item::ArrayOfTables(tables), Some(i)) => {
+            tables.get(i).and_then(|it| locate(it, &rest[1..]))
+        }
+        (Item::Value(Value::Array(array)), Some(i)) => array
+            .get(i)
+            .and_then(|it| it.as_inline_table())
+            .and_then(|it| locate(it, &rest[1..])),
+        _ => item.as_table_like().and_then(|it| locate(it, rest)),
+    };
+
+    found.or(key.span())
+}
+
+// Find the program the way it is looked up when the project starts
+fn find_executable(project: &Project, program: &str) -> Result<PathBuf, anyhow::Error> {
+    let is_executable = |path: &Path| {
+        std::fs::metadata(path).is_ok_and(|it| it.is_file() && it.permissions().mode() & 0o111 != 0)
+    };
+
+    if program.contains('/') {
+        let path: PathBuf = Path::new(&project.cwd)
+            .join(program)
+            .components()
+            .filter(|it| *it != Component::CurDir)
+            .collect();
+        return match is_executable(&path) {
+            true => Ok(path),
+            false => Err(anyhow!("{} is not an executable", path.display())),
+        };
+    }
+
+    let path = project
+        .envs
+        .as_ref()
+        .and_then(|it| it.get("PATH").cloned())
+        .or_else(|| std::env::var("PATH").ok())
+        .unwrap_or_default();
+
+    std::env::split_paths(&path)
+        .map(|it| it.join(program))
+        .find(|it| is_executable(it))
+        .with_context(|| format!("{} is not an executable on PATH", program))
+}
@@ -0,0 +1,226 @@
+use std::{
+    collections::HashMap,
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use glob::Pattern;
+
+use crate::{
+    config::{Project, WorkerConfig},
+    libc::{inotify_add_watch, inotify_init, inotify_read},
+};
+
+const DEFAULT_DEBOUNCE_MS: u64 = 300;
+const TICK: Duration = Duration::from_millis(50);
+
+/// Watches the files of a project, and tells when they have changed
+pub struct Watcher {
+    pub project: Project,
+    inotify: OwnedFd,
+    // The path of each watch descriptor
+    watches: HashMap<i32, PathBuf>,
+    ignore: Vec<Pattern>,
+    worker_dir: PathBuf,
+    debounce: Duration,
+    changes: Vec<PathBuf>,
+    changed_at: Option<Instant>,
+}
+
+impl Watcher {
+    pub fn new(config: &WorkerConfig, project: Project) -> Result<Self, anyhow::Error> {
+        if cfg!(not(target_os = "linux")) {
+            return Err(anyhow!("Watching files is only supported on Linux"));
+        }
+
+        let watch = project.watch.clone().unwrap_or_default();
+        let mut ignore = Vec::new();
+        for pattern in watch.ignore.iter().flatten() {
+            ignore.push(
+                Pattern::new(pattern)
+                    .with_context(|| format!("Invalid ignore pattern {}", pattern))?,
+            );
+            // `dir/**` ignores the directory itself too, so it is not watched at all
+            if let Some(dir) = pattern.strip_suffix("/**") {
+                ignore.extend(Pattern::new(dir).ok());
+            }
+        }
+
+        let mut watcher = Self {
+            inotify: inotify_init().map_err(|_| {
+                anyhow!(
+                    "Couldn't create inotify instance: {}",
+                    std::io::Error::last_os_error()
+                )
+            })?,
+            watches: HashMap::new(),
+            ignore,
+            worker_dir: config.worker_dir().to_path_buf(),
+            debounce: Duration::from_millis(watch.debounce_ms.unwrap_or(DEFAULT_DEBOUNCE_MS)),
+            changes: Vec::new(),
+            changed_at: None,
+            project,
+        };
+
+        let paths = watch.paths.unwrap_or(vec![".".to_string()]);
+        for path in paths {
+            let path = Path::new(&watcher.project.cwd).join(path);
+            if !path.exists() {
+                return Err(anyhow!("Can't watch {}, it does not exist", path.display()));
+            }
+            watcher.add(&path)?;
+        }
+
+        Ok(watcher)
+    }
+
+    // Watch the path, and every directory below it that is not ignored
+    fn add(&mut self, path: &Path) -> Result<(), anyhow::Error> {
+        if self.is_ignored(path) {
+            return Ok(());
+        }
+
+        let wd = inotify_add_watch(&self.inotify, path).map_err(|_| {
+            anyhow!(
+                "Couldn't watch {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            )
+        })?;
+        self.watches.insert(wd, path.to_path_buf());
+
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                let entry = entry?;
+                // Symlinks are not followed, to not watch the same directory twice
+                if entry.file_type()?.is_dir() {
+                    self.add(&entry.path())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_ignored(&self, path: &Path) -> bool {
+        // The logs and state of worker change all the time
+        if path.starts_with(&self.worker_dir) {
+            return true;
+        }
+
+        let relative = path.strip_prefix(&self.project.cwd).unwrap_or(path);
+        relative.ancestors().any(|path| {
+            self.ignore.iter().any(|pattern| {
+                pattern.matches_path(path)
+                    || (!pattern.as_str().contains('/')
+                        && path
+                            .file_name()
+                            .is_some_and(|name| pattern.matches(&name.to_string_lossy())))
+            })
+        })
+    }
+
+    /// Read the changes since the last call. Returns the changed files once no more changes have
+    /// come in for the debounce time
+    pub fn poll(&mut self) -> Result<Option<Vec<PathBuf>>, anyhow::Error> {
+        for event in inotify_read(&self.inotify) {
+            let Some(watched) = self.watches.get(&event.wd) else {
+                continue;
+            };
+            if event.removed {
+                self.watches.remove(&event.wd);
+                continue;
+            }
+
+            let path = match event.name.is_empty() {
+                true => watched.clone(),
+                false => watched.join(&event.name),
+            };
+            if self.is_ignored(&path) {
+                continue;
+            }
+
+            // Files in new directories can be written before the directory is watched, so the
+            // directory counts as a change itself
+            if event.new_dir {
+                self.add(&path)?;
+            }
+            if !self.changes.contains(&path) {
+                self.changes.push(path);
+            }
+            self.changed_at = Some(Instant::now());
+        }
+
+        match self.changed_at {
+            Some(at) if at.elapsed() >= self.debounce => {
+                self.changed_at = None;
+                Ok(Some(std::mem::take(&mut self.changes)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Start the projects, and restart them whenever their files change. Runs until interrupted.
+/// Without projects, every project with `watch` set is watched
+pub fn run(config: &WorkerConfig, projects: Vec<Project>) -> Result<(), anyhow::Error> {
+    let projects: Vec<_> = match projects.is_empty() {
+        true => config
+            .projects
+            .iter()
+            .filter(|it| it.watch.is_some())
+            .cloned()
+            .collect(),
+        false => projects,
+    };
+    if projects.is_empty() {
+        return Err(anyhow!(
+            "No projects to watch. Set `watch` on the projects to watch"
+        ));
+    }
+
+    let mut watchers = projects
+        .iter()
+        .map(|it| Watcher::new(config, it.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let (_, not_running) = config.partition_projects(projects)?;
+    crate::start(config, not_running, false)?;
+
+    for watcher in watchers.iter() {
+        println!("Watching {} for changes", watcher.project);
+    }
+
+    loop {
+        for watcher in watchers.iter_mut() {
+            let Some(changes) = watcher.poll()? else {
+                continue;
+            };
+
+            let project = watcher.project.clone();
+            let path = changes[0].strip_prefix(&project.cwd).unwrap_or(&changes[0]);
+            match changes.len() {
+                1 => println!("{} changed, restarting {}", path.display(), project),
+                n => println!(
+                    "{} and {} more changed, restarting {}",
+                    path.display(),
+                    n - 1,
+                    project
+                ),
+            }
+
+            // A project that has exited is started again, to pick up a fix for the crash
+            let res = match config.is_running(&project)? {
+                true => crate::restart(config, vec![project.clone()], false),
+                false => crate::start(config, vec![project.clone()], false),
+            };
+            if let Err(e) = res {
+                eprintln!("Couldn't restart {}: {}", project, e);
+            }
+        }
+
+        std::thread::sleep(TICK);
+    }
+}
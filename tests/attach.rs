@@ -0,0 +1,79 @@
+use common::WorkerTestConfig;
+use predicates::prelude::predicate;
+
+mod common;
+
+const REPL: &str = r#"
+[[project]]
+name = "repl"
+command = "sh -c 'echo ready; while read line; do echo \"got $line\"; done; echo eof'"
+stdin = "pipe"
+"#;
+
+#[test]
+fn test_attach_sends_input() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(REPL);
+    worker.cmd("start", &["repl"]).assert().success();
+
+    worker
+        .cmd("attach", &["repl"])
+        .write_stdin("hello\n~.\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("ready\n"))
+        .stdout(predicate::str::contains("got hello\n"))
+        .stderr(predicate::str::contains("Detached from repl"));
+
+    // Detaching doesn't end the input of the project
+    worker
+        .cmd("attach", &["repl"])
+        .write_stdin("again\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("got again\n"));
+
+    let log = std::fs::read_to_string(worker.log_dir().join("repl")).unwrap();
+    assert_eq!(log, "ready\ngot hello\ngot again\n");
+
+    worker
+        .cmd("status", &[])
+        .assert()
+        .stdout(predicate::str::contains("repl is running"));
+    worker.cmd("stop", &["repl"]).assert().success();
+}
+
+#[test]
+fn test_attach_without_pipe() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"reader\"\ncommand = \"sh -c 'cat; echo eof; sleep 30'\"\n",
+    );
+    worker.cmd("start", &["reader"]).assert().success();
+
+    worker
+        .cmd("attach", &["reader"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Can't attach to reader, it doesn't read input",
+        ));
+
+    // The input is empty without the pipe
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let log = std::fs::read_to_string(worker.log_dir().join("reader")).unwrap();
+    assert_eq!(log, "eof\n");
+    worker.cmd("stop", &["reader"]).assert().success();
+}
+
+#[test]
+fn test_attach_not_running() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(REPL);
+
+    worker
+        .cmd("attach", &["repl"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("repl is not running"));
+}
@@ -1,5 +1,8 @@
 #![allow(dead_code)]
-use std::fs::DirEntry;
+use std::{
+    fs::DirEntry,
+    time::{Duration, Instant},
+};
 
 use assert_cmd::{cargo::cargo_bin, Command};
 use sysinfo::{Pid, System};
@@ -94,9 +97,46 @@ impl WorkerTestConfig {
         }
     }
 
+    pub fn path(&self) -> &std::path::Path {
+        self.dir.path()
+    }
+
+    // Append raw toml to the config file, e.g. to add a project with a custom command
+    pub fn append_config(&self, config: &str) {
+        let path = self.dir.path().join(".worker.toml");
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(path, content + config).unwrap();
+    }
+
+    // Prepend raw toml to the config file, e.g. to set a top level key
+    pub fn prepend_config(&self, config: &str) {
+        let path = self.dir.path().join(".worker.toml");
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(path, config.to_string() + &content).unwrap();
+    }
+
+    // Write a file relative to the config dir, creating the directories leading to it
+    pub fn write_file(&self, path: &str, content: &str) {
+        let path = self.dir.path().join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    // Add an option to a project in the config file, e.g. `depends_on = [...]`
+    pub fn project_option(&self, project: WorkerTestProject, option: &str) {
+        let path = self.dir.path().join(".worker.toml");
+        let content = std::fs::read_to_string(&path).unwrap();
+        let name = format!("name = \"{}\"", self.project_name(&project));
+        let content = content.replacen(&name, &format!("{}\n{}", name, option), 1);
+        std::fs::write(path, content).unwrap();
+    }
+
     fn run(&self, command: &str, projects: Option<&[WorkerTestProject]>) -> Command {
         let mut cmd = Command::cargo_bin("worker").unwrap();
-        cmd.current_dir(&self.dir).arg(command);
+        // Keep the global config of the user out of the tests
+        cmd.current_dir(&self.dir)
+            .env("XDG_CONFIG_HOME", self.dir.path().join(".config"))
+            .arg(command);
 
         if let Some(projects) = projects {
             let projects = projects
@@ -133,6 +173,19 @@ impl WorkerTestConfig {
         self.run("status", None)
     }
 
+    // Run any subcommand with raw args
+    pub fn cmd(&self, command: &str, args: &[&str]) -> Command {
+        let mut cmd = self.run(command, None);
+        cmd.args(args);
+        cmd
+    }
+
+    pub fn daemon(&self, action: &str) -> Command {
+        let mut cmd = self.run("daemon", None);
+        cmd.arg(action);
+        cmd
+    }
+
     // Depends on `new()`. Used for asserting that the projects have actually started
     pub fn group_projects(&self, group: &WorkerTestProject) -> &[WorkerTestProject; 2] {
         match group {
@@ -170,6 +223,22 @@ impl WorkerTestConfig {
         }
     }
 
+    pub fn log_dir(&self) -> std::path::PathBuf {
+        self.dir.path().join(".worker/log")
+    }
+
+    // Wait until the log of `name` contains `expected` `count` times
+    pub fn wait_for_log(&self, name: &str, expected: &str, count: usize) {
+        let log = self.log_dir().join(name);
+        let start = Instant::now();
+        while Instant::now().duration_since(start) < Duration::new(2, 0) {
+            if std::fs::read_to_string(&log).is_ok_and(|it| it.matches(expected).count() >= count) {
+                return;
+            }
+        }
+        unreachable!("Couldn't find {:?} in the log in 2 seconds", expected)
+    }
+
     pub fn pids(&self, project: WorkerTestProject) -> Vec<Pid> {
         // Verify that the process is running using sysinfo
         let cmd = match project {
@@ -189,3 +258,12 @@ impl WorkerTestConfig {
             .collect()
     }
 }
+
+impl Drop for WorkerTestConfig {
+    // Make sure a daemon started by a test does not outlive it
+    fn drop(&mut self) {
+        if self.dir.path().join(".worker/daemon.sock").exists() {
+            let _ = self.daemon("stop").output();
+        }
+    }
+}
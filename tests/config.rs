@@ -0,0 +1,280 @@
+use common::{WorkerTestConfig, WorkerTestProject};
+use predicates::prelude::predicate;
+
+mod common;
+
+// The projects as listed by `worker list --format json`
+fn projects(worker: &WorkerTestConfig) -> Vec<serde_json::Value> {
+    let output = worker.cmd("list", &["--format", "json"]).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn project<'a>(projects: &'a [serde_json::Value], name: &str) -> &'a serde_json::Value {
+    projects.iter().find(|it| it["name"] == name).unwrap()
+}
+
+#[test]
+fn test_config_include_glob() {
+    let worker = WorkerTestConfig::new();
+    worker.prepend_config("include = [\"projects/*.toml\"]\n");
+    worker.write_file(
+        "projects/a.toml",
+        "[[project]]\nname = \"included-a\"\ncommand = \"sleep 5\"\ncwd = \"/\"\n",
+    );
+    worker.write_file(
+        "projects/b.toml",
+        "[[project]]\nname = \"included-b\"\ncommand = \"sleep 5\"\ncwd = \"/\"\n",
+    );
+
+    let projects = projects(&worker);
+    assert_eq!(projects.len(), 6);
+    assert_eq!(project(&projects, "included-b")["command"], "sleep 5");
+}
+
+#[test]
+fn test_config_include_missing() {
+    let worker = WorkerTestConfig::new();
+    worker.prepend_config("include = [\"missing.toml\"]\n");
+
+    worker
+        .list()
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("missing.toml"));
+}
+
+#[test]
+fn test_config_duplicate_project() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"twice\"\ncommand = \"sleep 5\"\n\n[[project]]\nname = \"twice\"\ncommand = \"sleep 6\"\n",
+    );
+
+    worker
+        .list()
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(".worker.toml"))
+        .stderr(predicate::str::contains(
+            "Project twice is defined more than once",
+        ));
+}
+
+#[test]
+fn test_config_include_itself() {
+    let worker = WorkerTestConfig::new();
+    worker.prepend_config("include = [\"other.toml\"]\n");
+    worker.write_file("other.toml", "include = [\".worker.toml\"]\n");
+
+    worker
+        .list()
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("includes itself"));
+}
+
+#[test]
+fn test_config_local_overrides() {
+    let worker = WorkerTestConfig::new();
+    let name = worker.project_name(&WorkerTestProject::One);
+    worker.project_option(
+        WorkerTestProject::One,
+        "envs = { SHARED = \"1\", OVERRIDDEN = \"shared\" }\ndisplay = \"Shared\"",
+    );
+    worker.write_file(
+        ".worker.local.toml",
+        &format!(
+            r#"
+            [[project]]
+            name = "{name}"
+            command = "sleep 5"
+            envs = {{ OVERRIDDEN = "local" }}
+            group = [ "mine" ]
+
+            [[project]]
+            name = "local"
+            command = "sleep 5"
+            cwd = "/"
+            "#
+        ),
+    );
+
+    let projects = projects(&worker);
+    assert_eq!(projects.len(), 5);
+
+    // Fields not in the local config are kept, and `envs` are merged key by key
+    let project = project(&projects, &name);
+    assert_eq!(project["command"], "sleep 5");
+    assert_eq!(project["display"], "Shared");
+    assert_eq!(project["groups"], serde_json::json!(["mine"]));
+
+    let mut cmd = worker.cmd("start", &[&name]);
+    cmd.assert().success();
+    let state =
+        std::fs::read_to_string(worker.state_file(WorkerTestProject::One).unwrap().path()).unwrap();
+    let state: serde_json::Value = serde_json::from_str(&state).unwrap();
+    assert_eq!(
+        state["envs"],
+        serde_json::json!({ "SHARED": "1", "OVERRIDDEN": "local" })
+    );
+}
+
+#[test]
+fn test_config_global_defaults() {
+    let worker = WorkerTestConfig::new();
+    worker.write_file(
+        ".config/worker/config.toml",
+        r#"
+        [log]
+        keep = 3
+        compress = true
+
+        [[project]]
+        name = "global"
+        command = "sleep 5"
+        cwd = "/"
+        "#,
+    );
+    worker.append_config("[log]\nkeep = 5\n");
+
+    let projects = projects(&worker);
+    assert_eq!(projects.len(), 5);
+    assert_eq!(project(&projects, "global")["command"], "sleep 5");
+}
+
+#[test]
+fn test_config_env_file_and_interpolation() {
+    let worker = WorkerTestConfig::new();
+    worker.prepend_config("env_file = [\".env\"]\n");
+    worker.write_file(
+        ".env",
+        "# Shared\nexport PORT=8080\nHOST=localhost # comment\nURL=\"http://${HOST}:${PORT}\"\nRAW='${PORT}'\n",
+    );
+    worker.write_file("local.env", "PORT=9090\n");
+    worker.append_config(
+        r#"
+        [[project]]
+        name = "interpolated"
+        command = "echo ${URL} ${MISSING:-fallback} $${PORT}"
+        cwd = "${WORKER_ROOT}"
+        env_file = [ "local.env" ]
+        envs = { NAME = "${PROJECT_NAME}-${PORT}" }
+        "#,
+    );
+
+    let projects = projects(&worker);
+    let project = project(&projects, "interpolated");
+    assert_eq!(
+        project["command"],
+        "echo http://localhost:8080 fallback ${PORT}"
+    );
+    assert_eq!(
+        project["cwd"],
+        worker
+            .path()
+            .canonicalize()
+            .unwrap()
+            .to_string_lossy()
+            .as_ref()
+    );
+
+    let mut cmd = worker.cmd("start", &["interpolated"]);
+    cmd.assert().success();
+    let state = std::fs::read_dir(worker.path().join(".worker/state"))
+        .unwrap()
+        .map(|it| it.unwrap().path())
+        .find(|it| it.to_string_lossy().contains("interpolated"))
+        .unwrap();
+    let state: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(state).unwrap()).unwrap();
+
+    // The env file of the project is loaded after the shared one
+    assert_eq!(state["envs"]["PORT"], "9090");
+    assert_eq!(state["envs"]["RAW"], "${PORT}");
+    assert_eq!(state["envs"]["NAME"], "interpolated-9090");
+}
+
+#[test]
+fn test_config_interpolation_not_set() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+        [[project]]
+        name = "shell"
+        command = "sh -c 'X=from-shell; echo ${X} ${SURELY_NOT_SET_IN_THE_ENVIRONMENT}.'"
+        cwd = "/"
+        "#,
+    );
+
+    // Variables that are not set are left for the shell
+    let projects = projects(&worker);
+    assert_eq!(
+        project(&projects, "shell")["command"],
+        "sh -c 'X=from-shell; echo ${X} ${SURELY_NOT_SET_IN_THE_ENVIRONMENT}.'"
+    );
+
+    worker
+        .cmd("run", &["shell"])
+        .env_remove("X")
+        .assert()
+        .success()
+        .stdout("from-shell .\n");
+}
+
+#[test]
+fn test_config_relative_cwd() {
+    let worker = WorkerTestConfig::new();
+    let root = worker.path().canonicalize().unwrap();
+    worker.write_file("sub/dir/.keep", "");
+    worker.append_config(
+        r#"
+        [[project]]
+        name = "relative"
+        command = "pwd"
+        cwd = "./sub/dir"
+
+        [[project]]
+        name = "default"
+        command = "pwd"
+
+        [[project]]
+        name = "home"
+        command = "pwd"
+        cwd = "~/dir"
+        "#,
+    );
+
+    let output = worker
+        .cmd("list", &["--format", "json"])
+        .env("HOME", root.join("sub"))
+        .output()
+        .unwrap();
+    let projects: Vec<serde_json::Value> = serde_json::from_slice(&output.stdout).unwrap();
+
+    let cwd = |name: &str| {
+        project(&projects, name)["cwd"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(cwd("relative"), root.join("sub/dir").to_string_lossy());
+    assert_eq!(cwd("default"), root.to_string_lossy());
+    assert_eq!(cwd("home"), root.join("sub/dir").to_string_lossy());
+
+    // The project is started in the directory, wherever worker is run from
+    let mut cmd = worker.cmd("start", &["relative"]);
+    cmd.current_dir(root.join("sub")).assert().success();
+
+    let log = worker.log_dir().join("relative");
+    let start = std::time::Instant::now();
+    while start.elapsed() < std::time::Duration::from_secs(1) {
+        if std::fs::read_to_string(&log).is_ok_and(|it| !it.is_empty()) {
+            break;
+        }
+    }
+    assert_eq!(
+        std::fs::read_to_string(&log).unwrap(),
+        format!("{}\n", root.join("sub/dir").to_string_lossy())
+    );
+}
@@ -0,0 +1,369 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    time::{Duration, Instant},
+};
+
+use assert_cmd::Command;
+use common::{WorkerTestConfig, WorkerTestProject};
+use predicates::prelude::predicate;
+
+mod common;
+
+#[test]
+fn test_daemon_start_and_stop() {
+    let worker = WorkerTestConfig::new();
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.daemon("status");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Daemon is running"));
+
+    let mut cmd = worker.daemon("stop");
+    cmd.assert().success();
+
+    let mut cmd = worker.daemon("status");
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_daemon_already_running() {
+    let worker = WorkerTestConfig::new();
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Daemon is already running"));
+}
+
+#[test]
+fn test_daemon_start_and_stop_project() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    assert!(worker.state_file(project).is_some());
+    assert_eq!(worker.pids(project).len(), 1);
+
+    let mut cmd = worker.daemon("status");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{} is running",
+            worker.project_name(&project)
+        )));
+
+    let mut cmd = worker.stop(&[project]);
+    cmd.assert().success();
+
+    assert!(worker.state_file(project).is_none());
+    assert_eq!(worker.pids(project).len(), 0);
+}
+
+#[test]
+fn test_daemon_records_exit_status() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::Two;
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    let mut cmd = worker.stop(&[project]);
+    cmd.assert().success();
+
+    let expected = format!("{} killed by signal 2", worker.project_name(&project));
+    let timeout = Duration::new(1, 0);
+    let start = Instant::now();
+
+    // The daemon reaps the project on its next tick
+    while Instant::now().duration_since(start) < timeout {
+        let output = worker.daemon("status").output().unwrap().stdout;
+        if String::from_utf8(output).unwrap().contains(&expected) {
+            return;
+        }
+    }
+    unreachable!("Daemon did not record the exit status in 1 second")
+}
+
+#[test]
+fn test_daemon_records_exit_status_captured() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::Two;
+
+    // The exit status is passed on by the process relaying the output
+    worker.project_option(project, "log = { capture = true }");
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    let mut cmd = worker.stop(&[project]);
+    cmd.assert().success();
+
+    wait_for_output(
+        || worker.daemon("status"),
+        &format!("{} killed by signal 2", worker.project_name(&project)),
+    );
+}
+
+#[test]
+fn test_daemon_records_last_run() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+        [[project]]
+        name = "failing"
+        command = "sh -c 'exit 3'"
+        cwd = "/"
+        restart = "never"
+        "#,
+    );
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("start", &["failing"]);
+    cmd.assert().success();
+
+    wait_for_output(
+        || worker.cmd("status", &["--all"]),
+        "failing exited with code 3 (",
+    );
+}
+
+// Run `cmd` until its stdout contains `expected`
+fn wait_for_output(cmd: impl Fn() -> Command, expected: &str) {
+    let timeout = Duration::new(5, 0);
+    let start = Instant::now();
+
+    while Instant::now().duration_since(start) < timeout {
+        let output = cmd().output().unwrap().stdout;
+        if String::from_utf8(output).unwrap().contains(expected) {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    unreachable!("Couldn't find {:?} in output in 5 seconds", expected)
+}
+
+#[test]
+fn test_daemon_restart_on_failure() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+        [[project]]
+        name = "crashing"
+        command = "sh -c 'exit 1'"
+        cwd = "/"
+        restart = "on-failure"
+        max_restarts = 2
+        restart_backoff_ms = 10
+        "#,
+    );
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("start", &["crashing"]);
+    cmd.assert().success();
+
+    wait_for_output(
+        || worker.daemon("status"),
+        "crashing exited with code 1 (restarts: 2)",
+    );
+}
+
+#[test]
+fn test_daemon_restart_never_on_success() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+        [[project]]
+        name = "succeeding"
+        command = "sh -c 'exit 0'"
+        cwd = "/"
+        restart = "on-failure"
+        restart_backoff_ms = 10
+        "#,
+    );
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("start", &["succeeding"]);
+    cmd.assert().success();
+
+    wait_for_output(
+        || worker.daemon("status"),
+        "succeeding exited with code 0\n",
+    );
+}
+
+#[test]
+fn test_status_shows_restarts() {
+    let worker = WorkerTestConfig::new();
+    let marker = worker.path().join("marker");
+    worker.append_config(&format!(
+        r#"
+        [[project]]
+        name = "flaky"
+        command = "sh -c 'test -f {0} && sleep 5; touch {0}; exit 3'"
+        cwd = "/"
+        restart = "always"
+        restart_backoff_ms = 10
+        "#,
+        marker.display()
+    ));
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("start", &["flaky"]);
+    cmd.assert().success();
+
+    wait_for_output(
+        || worker.status(),
+        "flaky is running (restarts: 1, last exited with code 3)",
+    );
+
+    let mut cmd = worker.cmd("stop", &["flaky"]);
+    cmd.assert().success();
+}
+
+#[test]
+fn test_daemon_start_wait_healthy() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.project_option(project, "healthcheck = { log = \"Hello from mock\" }");
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("start", &["--wait", &worker.project_name(&project)]);
+    cmd.assert().success();
+
+    assert_eq!(worker.pids(project).len(), 1);
+}
+
+#[test]
+fn test_daemon_killed_with_captured_project() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"captured\"\ncommand = \"sleep 30\"\nlog = { capture = true }\n\n[[project]]\nname = \"other\"\ncommand = \"sleep 30\"\n",
+    );
+
+    worker.daemon("start").assert().success();
+    worker.cmd("start", &["captured"]).assert().success();
+
+    let output = worker.daemon("status").output().unwrap();
+    let pid: i32 = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .and_then(|it| it.strip_prefix("Daemon is running with pid "))
+        .and_then(|it| it.parse().ok())
+        .unwrap();
+    unsafe { libc::kill(pid, libc::SIGKILL) };
+    std::thread::sleep(Duration::from_millis(200));
+
+    // The process in front of the project doesn't keep the socket of the daemon open
+    let start = Instant::now();
+    worker.cmd("start", &["other"]).assert().success();
+    worker
+        .cmd("stop", &["captured", "other"])
+        .assert()
+        .success();
+    assert!(start.elapsed() < Duration::from_secs(3));
+}
+
+#[test]
+fn test_daemon_start_already_running() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.daemon("start").assert().success();
+    worker.start(&[project]).assert().success();
+    let path = worker.state_file(project).unwrap().path();
+    let state = std::fs::read_to_string(&path).unwrap();
+
+    // A request to start the project that was sent before it was started, as the cli does
+    let mut stream =
+        std::os::unix::net::UnixStream::connect(worker.path().join(".worker/daemon.sock")).unwrap();
+    writeln!(stream, "{{\"Start\":[{}]}}", state).unwrap();
+    let mut response = String::new();
+    BufReader::new(&stream).read_line(&mut response).unwrap();
+    assert_eq!(response, "\"Ok\"\n");
+
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), state);
+    assert_eq!(worker.pids(project).len(), 1);
+    worker.stop(&[project]).assert().success();
+}
+
+#[test]
+fn test_daemon_restart_already_running() {
+    let worker = WorkerTestConfig::new();
+    // Fails the first time, and keeps running after that
+    worker.append_config(
+        r#"
+        [[project]]
+        name = "flaky"
+        command = "sh -c 'echo started >> starts; [ $(wc -l < starts) -gt 1 ] && exec sleep 30; exit 1'"
+        restart = "on-failure"
+        restart_backoff_ms = 1000
+        "#,
+    );
+
+    worker.daemon("start").assert().success();
+    worker.cmd("start", &["flaky"]).assert().success();
+    wait_for_output(|| worker.daemon("status"), "flaky exited with code 1");
+
+    // Started while the daemon waits to restart it
+    let mut run = std::process::Command::new(assert_cmd::cargo::cargo_bin("worker"))
+        .current_dir(worker.path())
+        .env("XDG_CONFIG_HOME", worker.path().join(".config"))
+        .args(["run", "flaky"])
+        .spawn()
+        .unwrap();
+    std::thread::sleep(Duration::from_millis(1500));
+
+    let starts = std::fs::read_to_string(worker.path().join("starts")).unwrap();
+    assert_eq!(starts.lines().count(), 2);
+
+    worker.cmd("stop", &["flaky"]).assert().success();
+    run.wait().unwrap();
+}
+
+#[test]
+fn test_daemon_stuck_client() {
+    let worker = WorkerTestConfig::new();
+    worker.daemon("start").assert().success();
+
+    // A client that never finishes its request
+    let mut stream =
+        std::os::unix::net::UnixStream::connect(worker.path().join(".worker/daemon.sock")).unwrap();
+    write!(stream, "{{\"Sta").unwrap();
+
+    let start = Instant::now();
+    worker
+        .daemon("status")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Daemon is running"));
+    assert!(start.elapsed() < Duration::from_secs(3));
+    worker.daemon("stop").assert().success();
+}
@@ -18,3 +18,30 @@ fn test_list_projects() {
         .stdout(predicate::str::contains(project2_name))
         .stdout(predicate::str::contains(project3_name));
 }
+
+#[test]
+fn test_list_format_json() {
+    let worker = WorkerTestConfig::new();
+    let output = worker.cmd("list", &["--format", "json"]).output().unwrap();
+    let projects: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(projects.as_array().unwrap().len(), 4);
+    assert_eq!(
+        projects[0]["name"],
+        worker.project_name(&WorkerTestProject::One)
+    );
+    assert_eq!(projects[0]["cwd"], "/");
+    assert_eq!(projects[0]["groups"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_list_format_yaml() {
+    let worker = WorkerTestConfig::new();
+    let mut cmd = worker.cmd("list", &["--format", "yaml"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "- name: {}\n",
+            worker.project_name(&WorkerTestProject::Two)
+        )));
+}
@@ -1,6 +1,7 @@
 use std::time::{Duration, Instant};
 
 use common::{WorkerTestConfig, WorkerTestProject};
+use predicates::prelude::predicate;
 
 mod common;
 
@@ -33,3 +34,465 @@ fn test_logs_success() {
     }
     unreachable!("Couldn't find output in 1 second")
 }
+
+// Start and stop the project once the mock has printed its output
+fn run_once(worker: &WorkerTestConfig, project: WorkerTestProject) {
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    let log = worker.log_dir().join(worker.project_name(&project));
+    let timeout = Duration::new(1, 0);
+    let start = Instant::now();
+    while Instant::now().duration_since(start) < timeout {
+        if std::fs::read_to_string(&log).is_ok_and(|it| it.contains("Hello from mock!")) {
+            break;
+        }
+    }
+
+    let mut cmd = worker.stop(&[project]);
+    cmd.assert().success();
+}
+
+#[test]
+fn test_logs_previous() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    let mut cmd = worker.cmd("logs", &["--previous", &worker.project_name(&project)]);
+    cmd.assert().failure();
+
+    run_once(&worker, project);
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("logs", &["--previous", &worker.project_name(&project)]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Hello from mock!"));
+}
+
+#[test]
+fn test_logs_keep_and_compress() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+    let name = worker.project_name(&project);
+
+    worker.append_config("[log]\nkeep = 2\ncompress = true\n");
+
+    for _ in 0..4 {
+        run_once(&worker, project);
+    }
+
+    let mut files: Vec<_> = std::fs::read_dir(worker.log_dir())
+        .unwrap()
+        .map(|it| it.unwrap().file_name().to_string_lossy().to_string())
+        .collect();
+    files.sort();
+
+    assert_eq!(
+        files,
+        vec![name.clone(), format!("{name}.1"), format!("{name}.2.gz")]
+    );
+}
+
+#[test]
+fn test_logs_rotate_on_start_disabled() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.project_option(project, "log = { rotate_on_start = false }");
+
+    run_once(&worker, project);
+    run_once(&worker, project);
+
+    let mut cmd = worker.cmd("logs", &["--previous", &worker.project_name(&project)]);
+    cmd.assert().failure();
+}
+
+const NOISY: &str = r#"
+    [[project]]
+    name = "noisy"
+    command = "sh -c 'head -c 20000 /dev/zero; sleep 5'"
+    cwd = "/"
+    log = { max_size = "10K" }
+"#;
+
+#[test]
+fn test_logs_rotate_max_size() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(NOISY);
+
+    let mut cmd = worker.daemon("start");
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("start", &["noisy"]);
+    cmd.assert().success();
+
+    assert_rotated(&worker);
+}
+
+#[test]
+fn test_logs_rotate_max_size_without_daemon() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(NOISY);
+
+    let mut cmd = worker.cmd("start", &["noisy"]);
+    cmd.assert().success();
+
+    assert_rotated(&worker);
+    worker.cmd("stop", &["noisy"]).assert().success();
+}
+
+fn assert_rotated(worker: &WorkerTestConfig) {
+    let timeout = Duration::new(2, 0);
+    let start = Instant::now();
+    while Instant::now().duration_since(start) < timeout {
+        if worker.log_dir().join("noisy.1").exists() {
+            let size = std::fs::metadata(worker.log_dir().join("noisy"))
+                .unwrap()
+                .len();
+            assert!(size < 10 * 1024);
+            return;
+        }
+    }
+    unreachable!("The log was not rotated in 2 seconds")
+}
+
+const TIMESTAMPED: &str = r#"
+    [[project]]
+    name = "timestamped"
+    command = "sh -c 'echo 2020-01-01T00:00:00Z old; echo continued; echo \"[2030-01-01 02:00:00+02:00] new\"; sleep 5'"
+    cwd = "/"
+"#;
+
+#[test]
+fn test_logs_lines_and_grep() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(TIMESTAMPED);
+
+    let mut cmd = worker.cmd("start", &["timestamped"]);
+    cmd.assert().success();
+    worker.wait_for_log("timestamped", "new", 1);
+
+    let mut cmd = worker.cmd("logs", &["-n", "1", "timestamped"]);
+    cmd.assert()
+        .success()
+        .stdout("[2030-01-01 02:00:00+02:00] new\n");
+
+    let mut cmd = worker.cmd("logs", &["--grep", "^cont", "timestamped"]);
+    cmd.assert().success().stdout("continued\n");
+}
+
+#[test]
+fn test_logs_since_until() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(TIMESTAMPED);
+
+    let mut cmd = worker.cmd("start", &["timestamped"]);
+    cmd.assert().success();
+    worker.wait_for_log("timestamped", "new", 1);
+
+    let mut cmd = worker.cmd("logs", &["--since", "2025-01-01", "timestamped"]);
+    cmd.assert()
+        .success()
+        .stdout("[2030-01-01 02:00:00+02:00] new\n");
+
+    // Lines without a timestamp belong to the line before them
+    let mut cmd = worker.cmd("logs", &["--until", "2025-01-01 00:00:00", "timestamped"]);
+    cmd.assert()
+        .success()
+        .stdout("2020-01-01T00:00:00Z old\ncontinued\n");
+
+    // The offset of the timestamp is taken into account
+    let mut cmd = worker.cmd("logs", &["--since", "2030-01-01 00:30:00", "timestamped"]);
+    cmd.assert().success().stdout("");
+
+    let mut cmd = worker.cmd("logs", &["--since", "yesterday", "timestamped"]);
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_logs_follow_across_restart() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+    let name = worker.project_name(&project);
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+    worker.wait_for_log(&name, "Hello from mock!", 1);
+
+    let mut follow = std::process::Command::new(assert_cmd::cargo::cargo_bin("worker"))
+        .current_dir(worker.path())
+        .args(["logs", "--follow", &name])
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // Restarting rotates and truncates the log
+    let mut cmd = worker.restart(&[project]);
+    cmd.assert().success();
+    worker.wait_for_log(&name, "Hello from mock!", 1);
+    std::thread::sleep(Duration::from_millis(300));
+
+    follow.kill().unwrap();
+    let output = follow.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(stdout.matches("Hello from mock!").count(), 2);
+}
+
+#[test]
+fn test_logs_group_prefixed() {
+    let worker = WorkerTestConfig::new();
+    let group = WorkerTestProject::GroupOne;
+    let [project1, project2] = *worker.group_projects(&group);
+
+    worker.project_option(project2, "display = \"Second\"");
+
+    let mut cmd = worker.start(&[group]);
+    cmd.assert().success();
+    worker.wait_for_log(&worker.project_name(&project1), "Hello from mock!", 1);
+    worker.wait_for_log(&worker.project_name(&project2), "Hello from mock!", 1);
+
+    // Names are padded to the longest one, and `display` is used when set
+    let name1 = worker.project_name(&project1);
+    let mut cmd = worker.cmd("logs", &[&worker.project_name(&group)]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{} | Hello from mock!",
+            name1
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{:width$} | Hello from mock!",
+            "Second",
+            width = name1.len()
+        )));
+}
+
+#[test]
+fn test_logs_multiple_one_not_running() {
+    let worker = WorkerTestConfig::new();
+    let project1 = WorkerTestProject::One;
+    let project2 = WorkerTestProject::Two;
+
+    let mut cmd = worker.start(&[project1]);
+    cmd.assert().success();
+    worker.wait_for_log(&worker.project_name(&project1), "Hello from mock!", 1);
+
+    let name1 = worker.project_name(&project1);
+    let name2 = worker.project_name(&project2);
+    let mut cmd = worker.cmd("logs", &[&name1, &name2]);
+    cmd.assert()
+        .success()
+        .stdout(format!("{} | Hello from mock!\n", name1))
+        .stderr(format!("{} is not running\n", name2));
+
+    let mut cmd = worker.cmd("logs", &[&name2, &name2]);
+    cmd.assert().failure();
+}
+
+const CAPTURED: &str = r#"
+[[project]]
+name = "noisy"
+command = "sh -c 'echo out; echo err >&2; sleep 5'"
+cwd = "/"
+log = { capture = true }
+"#;
+
+#[test]
+fn test_logs_capture() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(CAPTURED);
+
+    let mut cmd = worker.cmd("start", &["noisy"]);
+    cmd.assert().success();
+    worker.wait_for_log("noisy", "stdout out", 1);
+    worker.wait_for_log("noisy", "stderr err", 1);
+
+    // The time and stream are only printed when asked for
+    let mut cmd = worker.cmd("logs", &["noisy"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("out\n"));
+
+    let mut cmd = worker.cmd("logs", &["--stderr-only", "noisy"]);
+    cmd.assert().success().stdout("err\n");
+
+    let mut cmd = worker.cmd("logs", &["--timestamps", "--stderr-only", "noisy"]);
+    cmd.assert().success().stdout(
+        predicate::str::is_match(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\.\d{3}Z err\n$").unwrap(),
+    );
+
+    // The process relaying the output stays until the project has stopped
+    let mut cmd = worker.cmd("stop", &["noisy"]);
+    cmd.assert().success().stderr("");
+    worker.status().assert().stdout("");
+}
+
+#[test]
+fn test_logs_stderr_only_not_captured() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+    worker.wait_for_log(&worker.project_name(&project), "Hello from mock!", 1);
+
+    // The stream is not known unless the output is captured
+    let mut cmd = worker.cmd("logs", &["--stderr-only", &worker.project_name(&project)]);
+    cmd.assert().success().stdout("");
+}
+
+#[test]
+fn test_logs_format_json() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(CAPTURED);
+
+    let mut cmd = worker.cmd("start", &["noisy"]);
+    cmd.assert().success();
+    worker.wait_for_log("noisy", "stderr err", 1);
+
+    // One object per line, to be able to follow the log
+    let output = worker
+        .cmd("logs", &["--format", "json", "noisy"])
+        .output()
+        .unwrap();
+    let lines: Vec<serde_json::Value> = output
+        .stdout
+        .split(|it| *it == b'\n')
+        .filter(|it| !it.is_empty())
+        .map(|it| serde_json::from_slice(it).unwrap())
+        .collect();
+
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[1]["project"], "noisy");
+    assert_eq!(lines[1]["stream"], "stderr");
+    assert_eq!(lines[1]["line"], "err");
+    assert!(lines[1]["time"].is_string());
+}
+
+#[test]
+fn test_logs_without_time_in_read_order() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+[[project]]
+name = "tïmèd-long"
+command = "sh -c 'echo timed; sleep 5'"
+cwd = "/"
+log = { capture = true }
+
+[[project]]
+name = "plain"
+command = "sh -c 'echo plain; sleep 5'"
+cwd = "/"
+"#,
+    );
+
+    worker
+        .cmd("start", &["tïmèd-long", "plain"])
+        .assert()
+        .success();
+    worker.wait_for_log("tïmèd-long", "stdout timed", 1);
+    worker.wait_for_log("plain", "plain", 1);
+
+    // The plain line has no time, so the logs are printed in the order they were asked for
+    let output = worker
+        .cmd("logs", &["--format", "table", "tïmèd-long", "plain"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(
+        lines[0],
+        format!("{:24}  {:10}  STREAM  LINE", "TIME", "PROJECT")
+    );
+    assert!(lines[1].contains("  tïmèd-long  stdout  timed"));
+    assert_eq!(
+        lines[2],
+        format!("{:24}  {:10}  {:6}  plain", "-", "plain", "-")
+    );
+
+    worker
+        .cmd("stop", &["tïmèd-long", "plain"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_logs_tail_large() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+[[project]]
+name = "large"
+command = "sh -c 'echo 2020-01-01T00:00:00Z first; i=0; while [ $i -lt 20000 ]; do echo line $i; i=$((i+1)); done; echo done; sleep 5'"
+cwd = "/"
+"#,
+    );
+
+    worker.cmd("start", &["large"]).assert().success();
+    worker.wait_for_log("large", "done", 1);
+
+    worker
+        .cmd("logs", &["-n", "2", "large"])
+        .assert()
+        .success()
+        .stdout("line 19999\ndone\n");
+    worker
+        .cmd("logs", &["--grep", "^line 5$", "large"])
+        .assert()
+        .success()
+        .stdout("line 5\n");
+    // The lines at the end get their time from the first line
+    worker
+        .cmd("logs", &["-n", "1", "--until", "2025-01-01", "large"])
+        .assert()
+        .success()
+        .stdout("done\n");
+
+    worker.cmd("stop", &["large"]).assert().success();
+}
+
+#[test]
+fn test_logs_local_time() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+[[project]]
+name = "local"
+command = "sh -c 'echo 2030-01-01 12:00:00 naive; sleep 5'"
+cwd = "/"
+"#,
+    );
+
+    worker.cmd("start", &["local"]).assert().success();
+    worker.wait_for_log("local", "naive", 1);
+
+    // Two hours ahead of UTC, so the line is at 10:00 UTC
+    let logs = |args: &[&str]| {
+        let mut cmd = worker.cmd("logs", args);
+        cmd.env("TZ", "EET-2");
+        cmd
+    };
+    logs(&["--since", "2030-01-01T11:00:00Z", "local"])
+        .assert()
+        .success()
+        .stdout("");
+    logs(&["--until", "2030-01-01T10:30:00Z", "local"])
+        .assert()
+        .success()
+        .stdout("2030-01-01 12:00:00 naive\n");
+    logs(&["--since", "2030-01-01 11:30:00", "local"])
+        .assert()
+        .success()
+        .stdout("2030-01-01 12:00:00 naive\n");
+    logs(&["--since", "2030-01-01 12:30:00", "local"])
+        .assert()
+        .success()
+        .stdout("");
+
+    worker.cmd("stop", &["local"]).assert().success();
+}
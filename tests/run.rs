@@ -0,0 +1,147 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::Stdio,
+};
+
+use assert_cmd::cargo::cargo_bin;
+use common::WorkerTestConfig;
+use predicates::prelude::{predicate, PredicateBooleanExt};
+
+mod common;
+
+#[test]
+fn test_run_with_args_envs_and_cwd() {
+    let worker = WorkerTestConfig::new();
+    worker.write_file("sub/.keep", "");
+    worker.append_config(
+        "\n[[project]]\nname = \"greeter\"\ncommand = \"sh -c 'echo $GREETING $@; pwd' sh\"\ncwd = \"sub\"\nenvs = { GREETING = \"hi\" }\n",
+    );
+    let sub = worker.path().canonicalize().unwrap().join("sub");
+
+    worker
+        .cmd("run", &["greeter", "--", "a", "b"])
+        .assert()
+        .success()
+        .stdout(format!("hi a b\n{}\n", sub.display()));
+
+    // The log is left alone without `--log`
+    assert!(!worker.log_dir().join("greeter").exists());
+}
+
+#[test]
+fn test_run_exit_code_and_log() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"failing\"\ncommand = \"sh -c 'echo out; echo err >&2; exit 4'\"\n",
+    );
+
+    worker
+        .cmd("run", &["--log", "failing"])
+        .assert()
+        .code(4)
+        .stdout("out\n")
+        .stderr("err\n");
+
+    let log = std::fs::read_to_string(worker.log_dir().join("failing")).unwrap();
+    assert!(log.contains("out\n"));
+    assert!(log.contains("err\n"));
+
+    worker
+        .cmd("status", &["--all"])
+        .assert()
+        .stdout(predicate::str::contains("failing exited with code 4"));
+}
+
+#[test]
+fn test_run_forwards_signals() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+[[project]]
+name = "trapping"
+command = "sh -c 'trap \"echo got term; exit 7\" TERM; echo ready; while true; do sleep 0.1; done'"
+"#,
+    );
+
+    let mut child = std::process::Command::new(cargo_bin("worker"))
+        .current_dir(worker.path())
+        .env("XDG_CONFIG_HOME", worker.path().join(".config"))
+        .args(["run", "trapping"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "ready\n");
+
+    unsafe { libc::kill(child.id() as i32, libc::SIGTERM) };
+
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), Some(7));
+    line.clear();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "got term\n");
+}
+
+#[test]
+fn test_run_already_running() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config("\n[[project]]\nname = \"server\"\ncommand = \"sleep 30\"\n");
+
+    worker.cmd("start", &["server"]).assert().success();
+    worker
+        .cmd("run", &["server"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("server is already running"));
+    worker.cmd("stop", &["server"]).assert().success();
+}
+
+#[test]
+fn test_run_is_tracked() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"server\"\ncommand = \"sh -c 'echo ready; sleep 30; true'\"\n",
+    );
+
+    let mut child = std::process::Command::new(cargo_bin("worker"))
+        .current_dir(worker.path())
+        .env("XDG_CONFIG_HOME", worker.path().join(".config"))
+        .args(["run", "server"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "ready\n");
+
+    worker
+        .cmd("status", &["--tree"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("server is running"))
+        .stdout(predicate::str::contains("sleep 30"))
+        // Only the project and its child, and not worker, which shares the session with them
+        .stdout(predicate::str::contains("children 1"));
+    worker
+        .cmd("start", &["server"])
+        .assert()
+        .stderr(predicate::str::contains("server is already running"));
+
+    worker.cmd("stop", &["server"]).assert().success();
+    assert!(!child.wait().unwrap().success());
+    worker
+        .cmd("status", &[])
+        .assert()
+        .stdout(predicate::str::contains("server is running").not());
+    assert_eq!(
+        std::fs::read_dir(worker.path().join(".worker/state"))
+            .unwrap()
+            .count(),
+        0
+    );
+}
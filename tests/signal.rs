@@ -0,0 +1,72 @@
+use common::{WorkerTestConfig, WorkerTestProject};
+use predicates::prelude::predicate;
+
+mod common;
+
+const TRAPS: &str = r#"
+[[project]]
+name = "traps"
+command = "sh -c 'trap \"echo hup\" HUP; trap \"echo usr1\" USR1; trap \"echo term; exit 0\" TERM; echo ready; while true; do sleep 0.1; done'"
+cwd = "/"
+"#;
+
+#[test]
+fn test_signal_by_name_and_number() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(TRAPS);
+
+    let mut cmd = worker.cmd("start", &["traps"]);
+    cmd.assert().success();
+    worker.wait_for_log("traps", "ready", 1);
+
+    let mut cmd = worker.cmd("signal", &["traps", "usr1"]);
+    cmd.assert().success();
+    worker.wait_for_log("traps", "usr1", 1);
+
+    let mut cmd = worker.cmd("signal", &["traps", &libc::SIGUSR1.to_string()]);
+    cmd.assert().success();
+    worker.wait_for_log("traps", "usr1", 2);
+
+    let mut cmd = worker.cmd("reload", &["traps"]);
+    cmd.assert().success();
+    worker.wait_for_log("traps", "hup", 1);
+
+    // The project keeps running
+    worker
+        .status()
+        .assert()
+        .stdout(predicate::str::starts_with("traps is running"));
+
+    worker.cmd("stop", &["--force", "traps"]).assert().success();
+}
+
+#[test]
+fn test_signal_invalid() {
+    let worker = WorkerTestConfig::new();
+    let name = worker.project_name(&WorkerTestProject::One);
+
+    let mut cmd = worker.cmd("signal", &[&name, "SIGFOO"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown signal SIGFOO"));
+
+    let mut cmd = worker.cmd("signal", &[&name, "hup"]);
+    cmd.assert()
+        .success()
+        .stderr(format!("Cannot signal project not running: {}\n", name));
+}
+
+#[test]
+fn test_signal_stop_signal_lowercase() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(TRAPS);
+    worker.append_config("stop_signal = \"term\"\nstop_sequence = [{ signal = 9, after = 2 }]\n");
+
+    let mut cmd = worker.cmd("start", &["traps"]);
+    cmd.assert().success();
+    worker.wait_for_log("traps", "ready", 1);
+
+    let mut cmd = worker.cmd("stop", &["traps"]);
+    cmd.assert().success();
+    worker.wait_for_log("traps", "term", 1);
+}
@@ -1,4 +1,5 @@
 use common::{WorkerTestConfig, WorkerTestProject};
+use predicates::prelude::predicate;
 
 mod common;
 
@@ -142,3 +143,162 @@ fn test_start_multiple_groups() {
     assert_eq!(worker.pids(projects2[0]).len(), 1);
     assert_eq!(worker.pids(projects2[1]).len(), 1);
 }
+
+#[test]
+fn test_start_with_dependency() {
+    let worker = WorkerTestConfig::new();
+    let project1 = WorkerTestProject::One;
+    let project2 = WorkerTestProject::Two;
+
+    worker.project_option(
+        project1,
+        &format!("depends_on = [\"{}\"]", worker.project_name(&project2)),
+    );
+
+    let mut cmd = worker.start(&[project1]);
+    cmd.assert().success().stderr("");
+
+    // The dependency is started even if it was not asked for
+    assert!(worker.state_file(project1).is_some());
+    assert!(worker.state_file(project2).is_some());
+    assert_eq!(worker.pids(project1).len(), 1);
+    assert_eq!(worker.pids(project2).len(), 1);
+}
+
+#[test]
+fn test_start_with_dependency_already_running() {
+    let worker = WorkerTestConfig::new();
+    let project1 = WorkerTestProject::One;
+    let project2 = WorkerTestProject::Two;
+
+    worker.project_option(
+        project1,
+        &format!("depends_on = [\"{}\"]", worker.project_name(&project2)),
+    );
+
+    let mut cmd = worker.start(&[project2]);
+    cmd.assert().success();
+
+    let pid2 = worker.pids(project2)[0];
+
+    let mut cmd = worker.start(&[project1]);
+    cmd.assert().success().stderr("");
+
+    assert_eq!(worker.pids(project1).len(), 1);
+    assert_eq!(worker.pids(project2), vec![pid2]);
+}
+
+#[test]
+fn test_start_dependency_cycle() {
+    let worker = WorkerTestConfig::new();
+    let project1 = WorkerTestProject::One;
+    let project2 = WorkerTestProject::Two;
+
+    worker.project_option(
+        project1,
+        &format!("depends_on = [\"{}\"]", worker.project_name(&project2)),
+    );
+    worker.project_option(
+        project2,
+        &format!("depends_on = [\"{}\"]", worker.project_name(&project1)),
+    );
+
+    let mut cmd = worker.start(&[project1]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Dependency cycle"));
+
+    assert_eq!(worker.pids(project1).len(), 0);
+    assert_eq!(worker.pids(project2).len(), 0);
+}
+
+#[test]
+fn test_start_unknown_dependency() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.project_option(project, "depends_on = [\"unknown\"]");
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "depends on unknown project unknown",
+    ));
+}
+
+#[test]
+fn test_start_wait_healthy() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.project_option(project, "healthcheck = { log = \"Hello from mock\" }");
+
+    let mut cmd = worker.cmd("start", &["--wait", &worker.project_name(&project)]);
+    cmd.assert().success();
+
+    assert_eq!(worker.pids(project).len(), 1);
+}
+
+#[test]
+fn test_start_wait_unhealthy() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    // Nothing is listening on port 1
+    worker.project_option(
+        project,
+        "healthcheck = { tcp = \"127.0.0.1:1\", interval_ms = 100, timeout_secs = 1 }",
+    );
+
+    let mut cmd = worker.cmd("start", &["--wait", &worker.project_name(&project)]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("was not healthy after 1 seconds"));
+}
+
+#[test]
+fn test_start_invalid_healthcheck() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.project_option(
+        project,
+        "healthcheck = { tcp = \"127.0.0.1:1\", log = \"Hello\" }",
+    );
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("needs exactly one of"));
+}
+
+#[test]
+fn test_start_concurrently() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    // Only one of the workers should start the project, the rest should see it running
+    let children: Vec<_> = (0..8)
+        .map(|_| {
+            std::process::Command::new(assert_cmd::cargo::cargo_bin("worker"))
+                .current_dir(worker.path())
+                .env("XDG_CONFIG_HOME", worker.path().join(".config"))
+                .args(["start", &worker.project_name(&project)])
+                .stderr(std::process::Stdio::piped())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    let already_running = children
+        .into_iter()
+        .map(|it| it.wait_with_output().unwrap())
+        .filter(|it| String::from_utf8_lossy(&it.stderr).contains("is already running"))
+        .count();
+
+    assert_eq!(already_running, 7);
+    assert_eq!(worker.pids(project).len(), 1);
+
+    let state_files = std::fs::read_dir(worker.path().join(".worker/state"))
+        .unwrap()
+        .count();
+    assert_eq!(state_files, 1);
+}
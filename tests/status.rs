@@ -1,3 +1,5 @@
+use std::os::unix::process::CommandExt;
+
 use common::{WorkerTestConfig, WorkerTestProject};
 
 mod common;
@@ -56,3 +58,315 @@ fn test_status_multiple_running() {
     assert!(stdout.contains(&project2_name));
     assert!(!stdout.contains(&project3_name));
 }
+
+#[test]
+fn test_status_healthy() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.project_option(project, "healthcheck = { command = \"true\" }");
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    let mut cmd = worker.status();
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::starts_with(format!(
+            "{} is running (healthy)\n",
+            worker.project_name(&project)
+        )));
+}
+
+#[test]
+fn test_status_starting_and_unhealthy() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.project_option(
+        project,
+        "healthcheck = { command = \"false\", timeout_secs = 1 }",
+    );
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    let mut cmd = worker.status();
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::starts_with(format!(
+            "{} is running (starting)\n",
+            worker.project_name(&project)
+        )));
+
+    std::thread::sleep(std::time::Duration::from_millis(1100));
+
+    let mut cmd = worker.status();
+    cmd.assert()
+        .success()
+        .stdout(predicates::str::starts_with(format!(
+            "{} is running (unhealthy)\n",
+            worker.project_name(&project)
+        )));
+}
+
+#[test]
+fn test_status_format_json() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.project_option(project, "display = \"First\"");
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    let output = worker
+        .cmd("status", &["--format", "json"])
+        .output()
+        .unwrap();
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(status[0]["name"], worker.project_name(&project));
+    assert_eq!(status[0]["display"], "First");
+    assert!(status[0]["pid"].is_i64());
+    assert_eq!(status[0]["restarts"], 0);
+    assert!(status[0]["uptime_secs"].is_u64());
+}
+
+#[test]
+fn test_status_format_table() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("status", &["--format", "table"]);
+    cmd.assert().success().stdout(
+        predicates::str::is_match(format!(
+            "^NAME +STATE +SID +UPTIME +CPU +RSS +CHILDREN +HEALTH +RESTARTS\n{} +running +\\d+ +\\d+s +[\\d.]+% +[\\d.]+ [KM]iB +1 +- +0\n$",
+            worker.project_name(&project),
+        ))
+        .unwrap(),
+    );
+}
+
+#[test]
+fn test_status_resources_and_tree() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+    let name = worker.project_name(&project);
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    // The project runs under the process recording how it exits
+    let mut cmd = worker.cmd("status", &["--tree"]);
+    cmd.assert().success().stdout(
+        predicates::str::is_match(format!(
+            "^{} is running\n  sid \\d+, uptime \\d+s, cpu [\\d.]+%, rss [\\d.]+ [KM]iB, children 1\n  └─ \\d+ .*worker start {} \\(\\w+\\)\n     └─ \\d+ .*mock {} \\(\\w+\\)\n$",
+            name, name, name
+        ))
+        .unwrap(),
+    );
+
+    let output = worker
+        .cmd("status", &["--format", "json"])
+        .output()
+        .unwrap();
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let processes = status[0]["processes"].as_array().unwrap();
+    let pid = worker.pids(project)[0].as_u32();
+
+    assert_eq!(status[0]["children"], 1);
+    assert!(status[0]["rss_bytes"].as_u64().unwrap() > 0);
+    assert_eq!(processes.len(), 2);
+    assert!(processes.iter().any(|it| it["pid"] == pid));
+}
+
+#[test]
+fn test_status_all() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+        [[project]]
+        name = "failing"
+        command = "sh -c 'exit 3'"
+        cwd = "/"
+
+        [[project]]
+        name = "crashing"
+        command = "sh -c 'kill -SEGV $$'"
+        cwd = "/"
+        "#,
+    );
+    let project1 = WorkerTestProject::One;
+    let project2 = WorkerTestProject::Two;
+    let name1 = worker.project_name(&project1);
+    let name2 = worker.project_name(&project2);
+
+    let mut cmd = worker.start(&[project1, project2]);
+    cmd.assert().success();
+    let mut cmd = worker.cmd("start", &["failing", "crashing"]);
+    cmd.assert().success();
+    let mut cmd = worker.stop(&[project2]);
+    cmd.assert().success();
+
+    // Wait for the failing projects to have exited
+    std::thread::sleep(std::time::Duration::from_millis(200));
+
+    let output = worker.cmd("status", &["--all"]).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().filter(|it| !it.starts_with(' ')).collect();
+
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], format!("{} is running", name1));
+    assert!(lines[1].starts_with(&format!("{} is stopped (by ", name2)));
+    assert_eq!(
+        lines[2],
+        format!(
+            "{} was never started",
+            worker.project_name(&WorkerTestProject::Three)
+        )
+    );
+    assert!(lines[4].starts_with("failing exited with code 3 ("));
+    assert!(lines[5].starts_with("crashing crashed, killed by signal 11 ("));
+
+    let output = worker
+        .cmd("status", &["--all", "--format", "json"])
+        .output()
+        .unwrap();
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let states: Vec<_> = status
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|it| it["state"].as_str().unwrap())
+        .collect();
+
+    assert_eq!(
+        states,
+        vec!["running", "stopped", "stopped", "stopped", "exited", "crashed"]
+    );
+    assert_eq!(status[4]["exit"]["code"], 3);
+}
+
+#[test]
+fn test_status_state_record() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+    let name = worker.project_name(&project);
+
+    worker.start(&[project]).assert().success();
+
+    let entry = worker.state_file(project).unwrap();
+    assert_eq!(
+        entry.file_name().to_string_lossy(),
+        format!("{}.json", name)
+    );
+
+    let state: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(entry.path()).unwrap()).unwrap();
+    assert_eq!(state["name"], name.as_str());
+    // The project runs in the session the state is recorded for
+    let system = sysinfo::System::new_all();
+    let process = system.process(worker.pids(project)[0]).unwrap();
+    assert_eq!(
+        state["pid"].as_i64().unwrap(),
+        process.session_id().unwrap().as_u32() as i64
+    );
+    assert!(state["started_at_ms"].as_u64().unwrap() > 0);
+    assert_eq!(state["argv"][1], name.as_str());
+    assert_eq!(
+        state["boot_id"].as_str(),
+        std::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+            .ok()
+            .as_deref()
+            .map(str::trim)
+    );
+}
+
+#[test]
+fn test_status_state_from_other_boot() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+
+    worker.start(&[project]).assert().success();
+
+    let path = worker.state_file(project).unwrap().path();
+    let mut state: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let pid = state["pid"].as_i64().unwrap();
+    state["boot_id"] = "another boot".into();
+    std::fs::write(&path, state.to_string()).unwrap();
+
+    // The pid is not trusted after a reboot
+    worker.status().assert().success().stdout("");
+    assert!(worker.state_file(project).is_none());
+
+    unsafe { libc::killpg(pid as i32, libc::SIGKILL) };
+}
+
+#[test]
+fn test_status_odd_project_name() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config("\n[[project]]\nname = \"my app.v2\"\ncommand = \"sleep 10\"\n");
+
+    worker.cmd("start", &["my app.v2"]).assert().success();
+    assert!(worker
+        .path()
+        .join(".worker/state/my%20app%2Ev2.json")
+        .exists());
+    worker
+        .status()
+        .assert()
+        .stdout(predicates::str::starts_with("my app.v2 is running"));
+
+    worker.cmd("stop", &["my app.v2"]).assert().success();
+    worker.status().assert().stdout("");
+}
+
+#[test]
+fn test_status_legacy_state_file() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config("\n[[project]]\nname = \"old-app\"\ncommand = \"sleep 30\"\n");
+
+    // Started by an older version, which named the state file after the project and the pid,
+    // and only stored the project in it
+    let child = unsafe {
+        std::process::Command::new("sleep")
+            .arg("30")
+            .pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            })
+            .spawn()
+            .unwrap()
+    };
+    let pid = child.id();
+    std::fs::create_dir_all(worker.path().join(".worker/state")).unwrap();
+    std::fs::write(
+        worker.path().join(format!(".worker/state/old-app-{}", pid)),
+        r#"{"name":"old-app","command":"sleep 30","cwd":"/","display":null,"stop_signal":null,"envs":null,"group":null}"#,
+    )
+    .unwrap();
+
+    worker
+        .status()
+        .assert()
+        .success()
+        .stdout(predicates::str::starts_with("old-app is running"));
+    worker
+        .cmd("start", &["old-app"])
+        .assert()
+        .success()
+        .stderr("old-app is already running\n");
+
+    worker.cmd("stop", &["old-app"]).assert().success();
+    worker.status().assert().stdout("");
+    assert!(!worker
+        .path()
+        .join(format!(".worker/state/old-app-{}", pid))
+        .exists());
+}
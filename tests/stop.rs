@@ -1,4 +1,7 @@
+use std::time::{Duration, Instant};
+
 use common::{WorkerTestConfig, WorkerTestProject};
+use predicates::prelude::predicate;
 
 mod common;
 
@@ -182,3 +185,119 @@ fn test_stop_groups_and_project_success() {
     assert_eq!(worker.pids(projects1[1]).len(), 0);
     assert_eq!(worker.pids(project3).len(), 0);
 }
+
+#[test]
+fn test_stop_with_dependency() {
+    let worker = WorkerTestConfig::new();
+    let project1 = WorkerTestProject::One;
+    let project2 = WorkerTestProject::Two;
+
+    worker.project_option(
+        project1,
+        &format!("depends_on = [\"{}\"]", worker.project_name(&project2)),
+    );
+
+    let mut cmd = worker.start(&[project1]);
+    cmd.assert().success();
+
+    let mut cmd = worker.stop(&[project2, project1]);
+    cmd.assert().success().stderr("");
+
+    assert!(worker.state_file(project1).is_none());
+    assert!(worker.state_file(project2).is_none());
+    assert_eq!(worker.pids(project1).len(), 0);
+    assert_eq!(worker.pids(project2).len(), 0);
+}
+
+// A project ignoring SIGINT, which is the default stop signal
+const IGNORE_SIGINT: &str = r#"
+    [[project]]
+    name = "stubborn"
+    command = "sh -c 'trap \"\" INT; sleep 5'"
+    cwd = "/"
+"#;
+
+#[test]
+fn test_stop_escalation() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(&format!(
+        "{}stop_sequence = [{{ signal = \"SIGTERM\", after = 1 }}]\n",
+        IGNORE_SIGINT
+    ));
+
+    let mut cmd = worker.cmd("start", &["stubborn"]);
+    cmd.assert().success();
+
+    let start = Instant::now();
+    let mut cmd = worker.cmd("stop", &["stubborn"]);
+    cmd.assert().success().stderr("");
+
+    assert!(Instant::now().duration_since(start) >= Duration::from_secs(1));
+    worker.status().assert().stdout("");
+}
+
+#[test]
+fn test_stop_timeout() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(&format!("{}stop_timeout = 1\n", IGNORE_SIGINT));
+
+    let mut cmd = worker.cmd("start", &["stubborn"]);
+    cmd.assert().success();
+
+    let mut cmd = worker.cmd("stop", &["stubborn"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Was not able to stop stubborn"));
+
+    worker
+        .status()
+        .assert()
+        .stdout(predicate::str::starts_with("stubborn is running\n"));
+
+    let mut cmd = worker.cmd("stop", &["--force", "stubborn"]);
+    cmd.assert().success();
+}
+
+#[test]
+fn test_stop_force() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(IGNORE_SIGINT);
+
+    let mut cmd = worker.cmd("start", &["stubborn"]);
+    cmd.assert().success();
+
+    let start = Instant::now();
+    let mut cmd = worker.cmd("stop", &["--force", "stubborn"]);
+    cmd.assert().success();
+
+    assert!(Instant::now().duration_since(start) < Duration::from_secs(1));
+    worker.status().assert().stdout("");
+}
+
+#[test]
+fn test_stop_removed_from_config() {
+    let worker = WorkerTestConfig::new();
+    let project = WorkerTestProject::One;
+    let name = worker.project_name(&project);
+
+    let mut cmd = worker.start(&[project]);
+    cmd.assert().success();
+
+    worker.write_file(
+        ".worker.toml",
+        "[[project]]\nname = \"other\"\ncommand = \"sleep 10\"\n",
+    );
+
+    // Still shown and stopped from the state it was started with
+    worker
+        .cmd("status", &["--all"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!("{} is running", name)));
+
+    let mut cmd = worker.cmd("stop", &[&name]);
+    cmd.assert().success();
+
+    assert!(worker.state_file(project).is_none());
+    assert_eq!(worker.pids(project).len(), 0);
+}
@@ -0,0 +1,132 @@
+use std::time::SystemTime;
+
+use common::WorkerTestConfig;
+use predicates::prelude::predicate;
+
+mod common;
+
+#[test]
+fn test_task_run_in_foreground() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"migrate\"\nkind = \"task\"\ncommand = \"sh -c 'echo hello; echo oops >&2; exit 3'\"\n",
+    );
+
+    worker
+        .cmd("run", &["migrate"])
+        .assert()
+        .code(3)
+        .stdout("hello\n")
+        .stderr("oops\n");
+
+    let log = std::fs::read_to_string(worker.log_dir().join("migrate")).unwrap();
+    assert!(log.contains("hello\n"));
+    assert!(log.contains("oops\n"));
+
+    worker
+        .cmd("status", &["--all"])
+        .assert()
+        .stdout(predicate::str::contains(
+            "migrate exited with code 3 after 0s",
+        ));
+}
+
+#[test]
+fn test_task_dependency_finishes_first() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+[[project]]
+name = "migrate"
+kind = "task"
+command = "sh -c 'sleep 0.5; touch migrated'"
+
+[[project]]
+name = "api"
+command = "sleep 30"
+depends_on = ["migrate"]
+
+[[project]]
+name = "broken"
+kind = "task"
+command = "false"
+
+[[project]]
+name = "api2"
+command = "sleep 30"
+depends_on = ["broken"]
+"#,
+    );
+
+    worker.cmd("start", &["api"]).assert().success();
+    assert!(worker.path().join("migrated").exists());
+    worker
+        .status()
+        .assert()
+        .stdout(predicate::str::starts_with("api is running"));
+    worker.cmd("stop", &["api"]).assert().success();
+
+    worker
+        .cmd("start", &["api2"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Dependency of api2 failed: broken exited with code 1",
+        ));
+    worker.status().assert().stdout("");
+}
+
+#[test]
+fn test_task_schedule() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"yearly\"\nkind = \"task\"\ncommand = \"true\"\nschedule = \"0 0 1 jan *\"\n",
+    );
+
+    let year: u64 = humantime::format_rfc3339(SystemTime::now()).to_string()[..4]
+        .parse()
+        .unwrap();
+    let new_year = humantime::parse_rfc3339(&format!("{}-01-01T00:00:00Z", year + 1)).unwrap();
+    let new_year = new_year
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let output = worker
+        .cmd("status", &["--all", "--format", "json"])
+        .env("TZ", "UTC")
+        .output()
+        .unwrap();
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let yearly = status
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|it| it["name"] == "yearly")
+        .unwrap();
+    assert_eq!(yearly["next_run"].as_u64().unwrap(), new_year);
+
+    worker
+        .cmd("status", &["--all"])
+        .assert()
+        .stdout(predicate::str::contains(
+            "yearly was never started\n  next run in ",
+        ));
+}
+
+#[test]
+fn test_task_schedule_invalid() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"cleanup\"\nkind = \"task\"\ncommand = \"true\"\nschedule = \"*/0 * * *\"\n\n[[project]]\nname = \"server\"\ncommand = \"true\"\nschedule = \"@daily\"\n",
+    );
+
+    worker
+        .cmd("validate", &[])
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "Invalid schedule */0 * * *: expected 5 fields",
+        ))
+        .stdout(predicate::str::contains("Only tasks can be scheduled"));
+}
@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use common::WorkerTestConfig;
+use predicates::prelude::predicate;
+
+mod common;
+
+#[test]
+fn test_tty_output() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+[[project]]
+name = "colors"
+command = "sh -c '[ -t 1 ] && echo tty; [ -t 2 ] && echo tty >&2; stty size <&1; printf \"no newline\"'"
+tty = true
+"#,
+    );
+
+    worker.cmd("start", &["colors"]).assert().success();
+    std::thread::sleep(Duration::from_millis(500));
+
+    let log = std::fs::read_to_string(worker.log_dir().join("colors")).unwrap();
+    assert_eq!(log, "tty\ntty\n24 120\nno newline");
+
+    worker
+        .cmd("status", &["--all"])
+        .assert()
+        .stdout(predicate::str::contains("colors exited with code 0"));
+}
+
+#[test]
+fn test_tty_captured_input() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        r#"
+[[project]]
+name = "repl"
+command = "sh -c 'while read line; do [ -t 0 ] && echo \"got $line\"; done'"
+tty = true
+stdin = "pipe"
+log = { capture = true }
+"#,
+    );
+
+    worker.cmd("start", &["repl"]).assert().success();
+    worker
+        .cmd("attach", &["repl"])
+        .write_stdin("hello\n")
+        .assert()
+        .success()
+        .stdout("got hello\n");
+
+    // The input is not echoed to the log
+    worker.cmd("logs", &["repl"]).assert().stdout("got hello\n");
+
+    worker.cmd("stop", &["repl"]).assert().success();
+}
@@ -0,0 +1,13 @@
+use common::WorkerTestConfig;
+use predicates::prelude::predicate;
+
+mod common;
+
+#[test]
+fn test_ui_not_a_terminal() {
+    let worker = WorkerTestConfig::new();
+    let mut cmd = worker.cmd("ui", &[]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("needs to run in a terminal"));
+}
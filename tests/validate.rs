@@ -0,0 +1,139 @@
+use common::{WorkerTestConfig, WorkerTestProject};
+use predicates::prelude::predicate;
+
+mod common;
+
+#[test]
+fn test_validate_success() {
+    let worker = WorkerTestConfig::new();
+    let mut cmd = worker.cmd("validate", &[]);
+    cmd.assert().success().stdout("No problems found\n");
+}
+
+#[test]
+fn test_validate_problems() {
+    let worker = WorkerTestConfig::new();
+    let group = worker.project_name(&WorkerTestProject::GroupOne);
+    worker.prepend_config("colour = true\n");
+    worker.append_config(&format!(
+        r#"
+[[project]]
+name = "{group}"
+command = "surely-not-an-executable --port 80"
+cwd = "missing"
+stop_signal = "SIGFOO"
+healthcheck = {{ tcp = "localhost:80", retries = 3 }}
+
+[[project]]
+name = "unterminated"
+command = "sh -c 'echo"
+
+[[project]]
+name = "twice"
+command = "echo"
+
+[[project]]
+name = "twice"
+command = "echo"
+"#
+    ));
+
+    // The line of the last line containing `text`
+    let content = std::fs::read_to_string(worker.path().join(".worker.toml")).unwrap();
+    let lines: Vec<_> = content.lines().collect();
+    let line = |text: &str| lines.iter().rposition(|it| it.contains(text)).unwrap() + 1;
+    let path = worker.path().canonicalize().unwrap();
+
+    // The problem is reported for the projects in the group
+    let groups: Vec<_> = (0..lines.len())
+        .filter(|i| lines[*i].contains("group = [") && lines[*i].contains(&group))
+        .map(|i| i + 1)
+        .collect();
+
+    let mut cmd = worker.cmd("check", &[]);
+    cmd.assert()
+        .failure()
+        .stdout(format!(
+            r#".worker.toml:1: Unknown field colour
+.worker.toml:{}: Group {group} has the same name as a project
+.worker.toml:{}: Group {group} has the same name as a project
+.worker.toml:{}: surely-not-an-executable is not an executable on PATH
+.worker.toml:{}: {}/missing is not a directory
+.worker.toml:{}: Invalid signal "SIGFOO"
+.worker.toml:{}: Unknown field healthcheck.retries
+.worker.toml:{}: Couldn't parse command: sh -c 'echo
+.worker.toml:{}: Project twice is defined more than once
+"#,
+            groups[0],
+            groups[1],
+            line("surely-not"),
+            line("missing"),
+            path.display(),
+            line("SIGFOO"),
+            line("retries"),
+            line("sh -c"),
+            line("name = \"twice\""),
+        ))
+        .stderr(predicate::str::contains("Found 9 problems in the config"));
+}
+
+#[test]
+fn test_validate_included_file() {
+    let worker = WorkerTestConfig::new();
+    worker.prepend_config("include = [\"other.toml\"]\n");
+    worker.write_file(
+        "other.toml",
+        "[[project]]\nname = \"other\"\ncommand = \"sleep 5\"\nstop_sequence = [{ signal = \"SIGTERM\", after = 1 }, { signal = \"BAD\", after = 2 }]\n",
+    );
+
+    let mut cmd = worker.cmd("validate", &[]);
+    cmd.assert()
+        .failure()
+        .stdout("other.toml:4: Invalid signal \"BAD\"\n");
+
+    // Syntax errors are reported with the line too, also by other commands
+    worker.write_file(".worker.local.toml", "[[project]]\nname = [\n");
+    let mut cmd = worker.cmd("validate", &[]);
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::starts_with(".worker.local.toml:3: "));
+    worker
+        .list()
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(".worker.local.toml:3: "));
+}
+
+#[test]
+fn test_validate_watch() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"watched\"\ncommand = \"sleep 5\"\nwatch = { paths = [\"missing\"], ignore = [\"[\"] }\n",
+    );
+
+    let mut cmd = worker.cmd("validate", &[]);
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "Watched path missing does not exist",
+    ));
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains("Invalid ignore pattern ["));
+}
+
+#[test]
+fn test_validate_unset_variable() {
+    let worker = WorkerTestConfig::new();
+    worker.append_config(
+        "\n[[project]]\nname = \"shell\"\ncommand = \"sh -c 'echo ${SURELY_NOT_SET_IN_THE_ENVIRONMENT}'\"\n",
+    );
+    let line = std::fs::read_to_string(worker.path().join(".worker.toml"))
+        .unwrap()
+        .lines()
+        .count();
+
+    let mut cmd = worker.cmd("validate", &[]);
+    cmd.assert().failure().stdout(predicate::str::contains(format!(
+        ".worker.toml:{}: Variable SURELY_NOT_SET_IN_THE_ENVIRONMENT is not set, and is left as ${{SURELY_NOT_SET_IN_THE_ENVIRONMENT}}",
+        line
+    )));
+}
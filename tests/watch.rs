@@ -0,0 +1,99 @@
+use std::{
+    io::{BufRead, BufReader},
+    process::{Child, Stdio},
+    time::{Duration, Instant},
+};
+
+use assert_cmd::cargo::cargo_bin;
+use common::WorkerTestConfig;
+use predicates::prelude::predicate;
+
+mod common;
+
+// Run `worker watch` until it has started watching
+fn watch(
+    worker: &WorkerTestConfig,
+    args: &[&str],
+) -> (Child, BufReader<std::process::ChildStdout>) {
+    let mut child = std::process::Command::new(cargo_bin("worker"))
+        .current_dir(worker.path())
+        .env("XDG_CONFIG_HOME", worker.path().join(".config"))
+        .arg("watch")
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "Watching watched for changes\n");
+
+    (child, stdout)
+}
+
+fn pid(worker: &WorkerTestConfig) -> Option<i64> {
+    let state = std::fs::read_to_string(worker.path().join(".worker/state/watched.json")).ok()?;
+    serde_json::from_str::<serde_json::Value>(&state).ok()?["pid"].as_i64()
+}
+
+// Wait for the project to be running with another pid than `old`
+fn restarted(worker: &WorkerTestConfig, old: i64, within: Duration) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < within {
+        if pid(worker).is_some_and(|it| it != old) {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+#[test]
+fn test_watch_restarts_on_change() {
+    let worker = WorkerTestConfig::new();
+    worker.write_file("src/main.txt", "");
+    worker.append_config(
+        "\n[[project]]\nname = \"watched\"\ncommand = \"sleep 30\"\nwatch = { paths = [\"src\"], ignore = [\"*.tmp\", \"src/gen/**\"], debounce_ms = 100 }\n",
+    );
+
+    let (mut child, mut stdout) = watch(&worker, &[]);
+    let first = pid(&worker).unwrap();
+
+    // Ignored files
+    worker.write_file("src/notes.tmp", "");
+    worker.write_file("src/gen/out.txt", "");
+    worker.write_file("other.txt", "");
+    assert!(!restarted(&worker, first, Duration::from_millis(600)));
+
+    worker.write_file("src/main.txt", "changed");
+    assert!(restarted(&worker, first, Duration::from_secs(5)));
+    let mut line = String::new();
+    stdout.read_line(&mut line).unwrap();
+    assert_eq!(line, "src/main.txt changed, restarting watched\n");
+
+    // Files in new directories
+    let second = pid(&worker).unwrap();
+    worker.write_file("src/new/mod.txt", "");
+    assert!(restarted(&worker, second, Duration::from_secs(5)));
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+
+    // The project keeps running after the watch is stopped
+    worker
+        .status()
+        .assert()
+        .stdout(predicate::str::starts_with("watched is running"));
+    worker.cmd("stop", &["watched"]).assert().success();
+}
+
+#[test]
+fn test_watch_nothing_to_watch() {
+    let worker = WorkerTestConfig::new();
+    worker
+        .cmd("watch", &[])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("No projects to watch"));
+}